@@ -0,0 +1,201 @@
+//! Headless stress benchmark for the per-frame enemy systems. Run it with
+//! `cargo test --release five_thousand_enemies -- --ignored --nocapture`.
+
+use std::time::{Duration, Instant};
+
+use bevy::{ecs::schedule::ExecutorKind, prelude::*};
+use bevy_rapier2d::{prelude::*, rapier::geometry::CollisionEventFlags};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+use crate::{
+    combat::{
+        apply_damage, deal_contact_damage, handle_collisions, ContactDamage, DamageEvent,
+        DamageType, DeathEvent, Health, Invulnerability,
+    },
+    dungeon::{DungeonMap, Tile, TILE_SIZE},
+    dungeon_generator::generate,
+    enemy::{
+        despawn_dead_enemies, setup_enemy_movement, update_enemy_spatial_hash, Enemy,
+        EnemySpatialHash,
+    },
+    navigation::{rebuild_flow_field, update_flow_field, FlowField},
+    player::{Player, PlayerHitbox},
+    rng::GameRng,
+    spell::{handle_particle_contacts, Spell},
+    Score,
+};
+
+const ENEMY_COUNT: usize = 5_000;
+/// Player projectiles alive at once, each hitting an enemy a few times a frame.
+const SPELL_COUNT: usize = 500;
+const SPELL_HITS_PER_FRAME: usize = 2_000;
+const CONTACTS_PER_FRAME: usize = 50;
+const FRAMES: usize = 300;
+const FRAME_TIME: f32 = 1. / 60.;
+
+/// Stands in for rapier, which the benchmark leaves out.
+fn integrate_velocities(mut query: Query<(&mut Transform, &Velocity)>) {
+    for (mut transform, velocity) in query.iter_mut() {
+        transform.translation += (velocity.linvel * FRAME_TIME).extend(0.);
+    }
+}
+
+fn schedule() -> Schedule {
+    let mut schedule = Schedule::new();
+    schedule.set_executor_kind(ExecutorKind::SingleThreaded);
+    schedule
+}
+
+fn report(name: &str, frame_times: &mut [Duration]) {
+    frame_times.sort();
+    let total: Duration = frame_times.iter().sum();
+    println!(
+        "{name:>8}: mean {:?}, median {:?}, worst {:?}",
+        total / frame_times.len() as u32,
+        frame_times[frame_times.len() / 2],
+        frame_times[frame_times.len() - 1],
+    );
+}
+
+#[test]
+#[ignore = "stress benchmark, run it in release mode"]
+fn five_thousand_enemies() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let map = generate(&mut rng);
+    let mut world = World::new();
+    world.init_resource::<FlowField>();
+    world.init_resource::<EnemySpatialHash>();
+    world.init_resource::<Events<CollisionEvent>>();
+    world.init_resource::<Events<DamageEvent>>();
+    world.init_resource::<Events<DeathEvent>>();
+    world.init_resource::<Score>();
+    world.init_resource::<Time>();
+    world.insert_resource(GameRng::new(0));
+
+    let mut floor = Vec::new();
+    for y in 0..map.height as i32 {
+        for x in 0..map.width as i32 {
+            let cell = IVec2::new(x, y);
+            let transform = Transform::from_translation(DungeonMap::cell_center(cell).extend(0.));
+            if map.get(cell).is_walkable() {
+                floor.push(transform.translation);
+            } else if map.get(cell) == Tile::Wall {
+                world.spawn((
+                    RigidBody::Fixed,
+                    Collider::cuboid(TILE_SIZE / 2., TILE_SIZE / 2.),
+                    GlobalTransform::from(transform),
+                    transform,
+                ));
+            }
+        }
+    }
+
+    let spawn = DungeonMap::cell_center(map.spawn.as_ivec2()).extend(0.);
+    let hitbox = world.spawn(PlayerHitbox).id();
+    world
+        .spawn((
+            Transform::from_translation(spawn),
+            Player { destination: spawn },
+            Health {
+                total: usize::MAX,
+                current: usize::MAX,
+            },
+            TextureAtlasSprite::default(),
+            Invulnerability::new(0.6),
+            ContactDamage::default(),
+        ))
+        .push_children(&[hitbox]);
+
+    let enemies: Vec<Entity> = (0..ENEMY_COUNT)
+        .map(|_| {
+            let jitter = Vec2::new(rng.gen_range(-12. ..12.), rng.gen_range(-12. ..12.));
+            let translation = *floor.choose(&mut rng).unwrap() + jitter.extend(0.);
+            world
+                .spawn((
+                    Transform::from_translation(translation),
+                    Velocity::zero(),
+                    Enemy {
+                        speed: 70.,
+                        radius: 12.,
+                        contact_damage: 1.,
+                        score_value: 1,
+                        experience: 1,
+                    },
+                    Health {
+                        total: 1_000,
+                        current: 1_000,
+                    },
+                    TextureAtlasSprite::default(),
+                ))
+                .id()
+        })
+        .collect();
+    let spells: Vec<Entity> = (0..SPELL_COUNT)
+        .map(|_| {
+            let spell = Spell::new(1, DamageType::Fire)
+                .with_pierce(u32::MAX)
+                .with_crit_chance(0.05);
+            world.spawn(spell).id()
+        })
+        .collect();
+
+    let mut movement = schedule();
+    movement.add_systems(
+        (
+            rebuild_flow_field,
+            update_flow_field,
+            update_enemy_spatial_hash,
+            setup_enemy_movement,
+        )
+            .chain(),
+    );
+    let mut combat = schedule();
+    combat.add_systems(
+        (
+            handle_collisions,
+            deal_contact_damage,
+            handle_particle_contacts,
+            apply_damage,
+            despawn_dead_enemies,
+        )
+            .chain(),
+    );
+    let mut physics = schedule();
+    physics.add_system(integrate_velocities);
+
+    let mut movement_times = Vec::with_capacity(FRAMES);
+    let mut combat_times = Vec::with_capacity(FRAMES);
+    for _ in 0..FRAMES {
+        world.resource_mut::<Time>().update();
+        world.resource_mut::<Events<CollisionEvent>>().update();
+        world.resource_mut::<Events<DamageEvent>>().update();
+        world.resource_mut::<Events<DeathEvent>>().update();
+        let mut collision_events = world.resource_mut::<Events<CollisionEvent>>();
+        for _ in 0..SPELL_HITS_PER_FRAME {
+            collision_events.send(CollisionEvent::Started(
+                *spells.choose(&mut rng).unwrap(),
+                *enemies.choose(&mut rng).unwrap(),
+                CollisionEventFlags::empty(),
+            ));
+        }
+        for _ in 0..CONTACTS_PER_FRAME {
+            collision_events.send(CollisionEvent::Started(
+                *enemies.choose(&mut rng).unwrap(),
+                hitbox,
+                CollisionEventFlags::empty(),
+            ));
+        }
+
+        let start = Instant::now();
+        movement.run(&mut world);
+        movement_times.push(start.elapsed());
+        let start = Instant::now();
+        combat.run(&mut world);
+        combat_times.push(start.elapsed());
+        physics.run(&mut world);
+    }
+
+    println!("{ENEMY_COUNT} enemies, {FRAMES} frames");
+    report("movement", &mut movement_times);
+    report("combat", &mut combat_times);
+}
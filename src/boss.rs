@@ -0,0 +1,441 @@
+use std::f32::consts::TAU;
+
+use bevy::{prelude::*, sprite::MaterialMesh2dBundle};
+use serde::Deserialize;
+
+use crate::{
+    combat::{CombatSet, DamageEvent, DamageType, Health},
+    enemy::{spawn_enemy, Enemy, EnemyKind, EnemyRegistry},
+    game_state::AppState,
+    player::Player,
+    sprite_sheets::SpriteSheetsMaps,
+};
+
+const BOSS_BAR_WIDTH: f32 = 480.;
+const BOSS_BAR_HEIGHT: f32 = 18.;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub enum BossKind {
+    Cyclops,
+    Demon,
+}
+
+/// Where a telegraphed area attack lands.
+#[derive(Clone, Copy)]
+pub enum AreaPattern {
+    /// One circle on the player's current position.
+    AtPlayer,
+    /// A single large circle around the boss.
+    Nova,
+    /// `count` circles evenly spaced at `distance` around the boss.
+    Ring { count: usize, distance: f32 },
+}
+
+pub struct AreaAttack {
+    pub pattern: AreaPattern,
+    pub radius: f32,
+    pub damage: usize,
+    /// Seconds between the warning showing up and the damage landing.
+    pub telegraph: f32,
+    /// Seconds between two attacks.
+    pub cooldown: f32,
+}
+
+pub struct BossPhase {
+    /// The phase starts once the boss drops to this fraction of its health.
+    pub health_threshold: f32,
+    pub speed_multiplier: f32,
+    pub attacks: &'static [AreaAttack],
+}
+
+impl BossKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            BossKind::Cyclops => "The Cyclops",
+            BossKind::Demon => "Red Demon",
+        }
+    }
+
+    pub fn enemy_kind(&self) -> EnemyKind {
+        match self {
+            BossKind::Cyclops => EnemyKind::Cyclops,
+            BossKind::Demon => EnemyKind::Demon,
+        }
+    }
+
+    /// Phases ordered from full health down.
+    fn phases(&self) -> &'static [BossPhase] {
+        match self {
+            BossKind::Cyclops => &CYCLOPS_PHASES,
+            BossKind::Demon => &DEMON_PHASES,
+        }
+    }
+}
+
+const CYCLOPS_PHASES: [BossPhase; 2] = [
+    BossPhase {
+        health_threshold: 1.,
+        speed_multiplier: 1.,
+        attacks: &[AreaAttack {
+            pattern: AreaPattern::AtPlayer,
+            radius: 60.,
+            damage: 2,
+            telegraph: 1.2,
+            cooldown: 4.,
+        }],
+    },
+    BossPhase {
+        health_threshold: 0.5,
+        speed_multiplier: 1.5,
+        attacks: &[
+            AreaAttack {
+                pattern: AreaPattern::AtPlayer,
+                radius: 60.,
+                damage: 2,
+                telegraph: 1.,
+                cooldown: 3.,
+            },
+            AreaAttack {
+                pattern: AreaPattern::Nova,
+                radius: 140.,
+                damage: 3,
+                telegraph: 1.5,
+                cooldown: 7.,
+            },
+        ],
+    },
+];
+
+const DEMON_PHASES: [BossPhase; 3] = [
+    BossPhase {
+        health_threshold: 1.,
+        speed_multiplier: 1.,
+        attacks: &[AreaAttack {
+            pattern: AreaPattern::Ring {
+                count: 6,
+                distance: 120.,
+            },
+            radius: 45.,
+            damage: 2,
+            telegraph: 1.2,
+            cooldown: 4.,
+        }],
+    },
+    BossPhase {
+        health_threshold: 0.6,
+        speed_multiplier: 1.25,
+        attacks: &[
+            AreaAttack {
+                pattern: AreaPattern::Ring {
+                    count: 8,
+                    distance: 160.,
+                },
+                radius: 45.,
+                damage: 2,
+                telegraph: 1.,
+                cooldown: 3.5,
+            },
+            AreaAttack {
+                pattern: AreaPattern::AtPlayer,
+                radius: 70.,
+                damage: 3,
+                telegraph: 1.,
+                cooldown: 3.,
+            },
+        ],
+    },
+    BossPhase {
+        health_threshold: 0.25,
+        speed_multiplier: 1.6,
+        attacks: &[
+            AreaAttack {
+                pattern: AreaPattern::Nova,
+                radius: 180.,
+                damage: 4,
+                telegraph: 1.5,
+                cooldown: 6.,
+            },
+            AreaAttack {
+                pattern: AreaPattern::AtPlayer,
+                radius: 70.,
+                damage: 3,
+                telegraph: 0.8,
+                cooldown: 2.,
+            },
+        ],
+    },
+];
+
+#[derive(Component)]
+pub struct Boss {
+    pub kind: BossKind,
+    /// Index into `BossKind::phases`.
+    phase: usize,
+    base_speed: f32,
+    /// One cooldown per attack of the current phase.
+    attack_timers: Vec<Timer>,
+}
+
+impl Boss {
+    fn current_phase(&self) -> &'static BossPhase {
+        &self.kind.phases()[self.phase]
+    }
+
+    fn enter_phase(&mut self, phase: usize) {
+        self.phase = phase;
+        self.attack_timers = self
+            .current_phase()
+            .attacks
+            .iter()
+            .map(|attack| Timer::from_seconds(attack.cooldown, TimerMode::Repeating))
+            .collect();
+    }
+}
+
+/// A warning circle on the ground. Anything inside it when the timer runs out
+/// gets hit.
+#[derive(Component)]
+struct Telegraph {
+    source: Entity,
+    radius: f32,
+    damage: usize,
+    timer: Timer,
+}
+
+#[derive(Component)]
+struct BossBar;
+
+#[derive(Component)]
+struct BossBarFill;
+
+pub struct BossPlugin;
+
+impl Plugin for BossPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            (
+                update_boss_phases,
+                start_area_attacks.after(update_boss_phases),
+                resolve_telegraphs.before(CombatSet::ResolveDamage),
+                spawn_boss_bar,
+                update_boss_bar,
+            )
+                .in_set(OnUpdate(AppState::Playing)),
+        );
+    }
+}
+
+pub fn spawn_boss(
+    commands: &mut Commands,
+    registry: &EnemyRegistry,
+    sprite_sheets_maps: &SpriteSheetsMaps,
+    kind: BossKind,
+    translation: Vec3,
+) -> Entity {
+    let enemy_kind = kind.enemy_kind();
+    let entity = spawn_enemy(
+        commands,
+        registry,
+        sprite_sheets_maps,
+        enemy_kind,
+        translation,
+    );
+    let mut boss = Boss {
+        kind,
+        phase: 0,
+        base_speed: registry.get(enemy_kind).speed,
+        attack_timers: Vec::new(),
+    };
+    boss.enter_phase(0);
+    commands
+        .entity(entity)
+        .insert((boss, Name::from(kind.name())));
+    entity
+}
+
+fn update_boss_phases(mut query: Query<(&mut Boss, &mut Enemy, &Health), Changed<Health>>) {
+    for (mut boss, mut enemy, health) in query.iter_mut() {
+        let fraction = health.current as f32 / health.total as f32;
+        let phase = boss
+            .kind
+            .phases()
+            .iter()
+            .rposition(|phase| fraction <= phase.health_threshold)
+            .unwrap_or(0);
+        if phase > boss.phase {
+            boss.enter_phase(phase);
+            enemy.speed = boss.base_speed * boss.current_phase().speed_multiplier;
+        }
+    }
+}
+
+fn start_area_attacks(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    player_query: Query<&Transform, With<Player>>,
+    mut query: Query<(Entity, &Transform, &mut Boss)>,
+    time: Res<Time>,
+) {
+    let player_translation = player_query.single().translation.truncate();
+    for (entity, transform, mut boss) in query.iter_mut() {
+        let boss_translation = transform.translation.truncate();
+        let attacks = boss.current_phase().attacks;
+        for (attack, timer) in attacks.iter().zip(boss.attack_timers.iter_mut()) {
+            if !timer.tick(time.delta()).just_finished() {
+                continue;
+            }
+            let centers = match attack.pattern {
+                AreaPattern::AtPlayer => vec![player_translation],
+                AreaPattern::Nova => vec![boss_translation],
+                AreaPattern::Ring { count, distance } => (0..count)
+                    .map(|index| {
+                        let angle = index as f32 / count as f32 * TAU;
+                        boss_translation + Vec2::from_angle(angle) * distance
+                    })
+                    .collect(),
+            };
+            for center in centers {
+                commands.spawn((
+                    MaterialMesh2dBundle {
+                        mesh: meshes.add(shape::Circle::new(attack.radius).into()).into(),
+                        material: materials.add(ColorMaterial::from(Color::rgba(1., 0., 0., 0.2))),
+                        // just above the floor, below every character
+                        transform: Transform::from_translation(center.extend(-0.5)),
+                        ..default()
+                    },
+                    Name::from("Telegraph"),
+                    Telegraph {
+                        source: entity,
+                        radius: attack.radius,
+                        damage: attack.damage,
+                        timer: Timer::from_seconds(attack.telegraph, TimerMode::Once),
+                    },
+                ));
+            }
+        }
+    }
+}
+
+fn resolve_telegraphs(
+    mut commands: Commands,
+    mut damage_events: EventWriter<DamageEvent>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    player_query: Query<(Entity, &Transform), With<Player>>,
+    mut query: Query<(Entity, &Transform, &Handle<ColorMaterial>, &mut Telegraph)>,
+    time: Res<Time>,
+) {
+    let (player_entity, player_transform) = player_query.single();
+    for (entity, transform, material, mut telegraph) in query.iter_mut() {
+        telegraph.timer.tick(time.delta());
+        // the warning fills in as the attack gets closer to landing
+        if let Some(material) = materials.get_mut(material) {
+            material.color.set_a(0.2 + 0.5 * telegraph.timer.percent());
+        }
+        if !telegraph.timer.finished() {
+            continue;
+        }
+        let distance = player_transform
+            .translation
+            .truncate()
+            .distance(transform.translation.truncate());
+        if distance < telegraph.radius {
+            damage_events.send(DamageEvent {
+                source: telegraph.source,
+                target: player_entity,
+                amount: telegraph.damage,
+                damage_type: DamageType::Explosion,
+                crit: false,
+            });
+        }
+        commands.entity(entity).despawn();
+    }
+}
+
+fn spawn_boss_bar(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    query: Query<&Boss, Added<Boss>>,
+    bar_query: Query<(), With<BossBar>>,
+) {
+    let Some(boss) = query.iter().next() else {
+        return;
+    };
+    if !bar_query.is_empty() {
+        return;
+    }
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        top: Val::Px(10.),
+                        left: Val::Percent(50.),
+                        ..default()
+                    },
+                    margin: UiRect::left(Val::Px(-BOSS_BAR_WIDTH / 2.)),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    gap: Size::all(Val::Px(4.)),
+                    ..default()
+                },
+                ..default()
+            },
+            BossBar,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                boss.kind.name(),
+                TextStyle {
+                    font: asset_server.load("fonts/DMSans-Regular.ttf"),
+                    font_size: 24.,
+                    color: Color::WHITE,
+                },
+            ));
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(BOSS_BAR_WIDTH), Val::Px(BOSS_BAR_HEIGHT)),
+                        ..default()
+                    },
+                    background_color: Color::rgb(0.15, 0.05, 0.05).into(),
+                    ..default()
+                })
+                .with_children(|bar| {
+                    bar.spawn((
+                        NodeBundle {
+                            style: Style {
+                                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                                ..default()
+                            },
+                            background_color: Color::rgb(0.8, 0.1, 0.1).into(),
+                            ..default()
+                        },
+                        BossBarFill,
+                    ));
+                });
+        });
+}
+
+/// Tracks the combined health of every living boss, and removes the bar once
+/// they are all dead.
+fn update_boss_bar(
+    mut commands: Commands,
+    boss_query: Query<&Health, With<Boss>>,
+    bar_query: Query<Entity, With<BossBar>>,
+    mut fill_query: Query<&mut Style, With<BossBarFill>>,
+) {
+    let Ok(bar) = bar_query.get_single() else {
+        return;
+    };
+    let (current, total) = boss_query.iter().fold((0, 0), |(current, total), health| {
+        (current + health.current, total + health.total)
+    });
+    if total == 0 {
+        commands.entity(bar).despawn_recursive();
+        return;
+    }
+    let mut style = fill_query.single_mut();
+    style.size.width = Val::Percent(current as f32 * 100. / total as f32);
+}
@@ -1,7 +1,27 @@
-use bevy::prelude::*;
-use bevy_rapier2d::prelude::CollisionEvent;
+use bevy::{prelude::*, utils::HashSet};
+use bevy_rapier2d::prelude::{CollisionEvent, Group};
+use serde::Deserialize;
 
-use crate::{enemy::Enemy, player::Player, utils::lerp};
+use crate::{
+    enemy::Enemy,
+    game_state::AppState,
+    player::{Player, PlayerHitbox},
+    utils::lerp,
+};
+
+/// The player's body. It is kept apart from the hitbox so it can later collide
+/// with level geometry without being hurt by it.
+pub const PLAYER_GROUP: Group = Group::GROUP_1;
+/// Sensor on the player that enemies and their projectiles hurt.
+pub const PLAYER_HITBOX_GROUP: Group = Group::GROUP_2;
+pub const PLAYER_SPELL_GROUP: Group = Group::GROUP_3;
+pub const ENEMY_GROUP: Group = Group::GROUP_4;
+pub const ENEMY_PROJECTILE_GROUP: Group = Group::GROUP_5;
+pub const WALL_GROUP: Group = Group::GROUP_6;
+
+const BLINK_INTERVAL: f32 = 0.1;
+const KNOCKBACK_SPEED: f32 = 260.;
+const KNOCKBACK_DURATION: f32 = 0.2;
 
 #[derive(Component)]
 struct HealthGlobe;
@@ -12,8 +32,38 @@ pub struct Health {
     pub current: usize,
 }
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+pub enum DamageType {
+    Physical,
+    #[default]
+    Fire,
+    Arcane,
+    Explosion,
+}
+
+/// Request to hurt `target`. Anything that deals damage sends one of these and
+/// lets `apply_damage` take care of health, the hit flash and deaths.
 pub struct DamageEvent {
+    pub source: Entity,
+    pub target: Entity,
+    pub amount: usize,
+    pub damage_type: DamageType,
+    /// A critical hit. `amount` already includes the bonus.
+    pub crit: bool,
+}
+
+/// Sent once when an entity's health reaches 0. The entity is still alive when
+/// the event is read, so listeners can look up its components.
+pub struct DeathEvent {
     pub entity: Entity,
+    /// Source of the killing blow. The same as `entity` for enemies that blew
+    /// themselves up.
+    pub source: Entity,
+}
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum CombatSet {
+    ResolveDamage,
 }
 
 #[derive(Component)]
@@ -29,15 +79,83 @@ impl Default for Damage {
     }
 }
 
+/// Ignores all damage for `duration` seconds after every hit, blinking
+/// meanwhile.
+#[derive(Component)]
+pub struct Invulnerability {
+    pub duration: f32,
+    remaining: f32,
+}
+
+impl Invulnerability {
+    pub fn new(duration: f32) -> Self {
+        Invulnerability {
+            duration,
+            remaining: 0.,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.remaining > 0.
+    }
+}
+
+/// Enemies touching the player's hitbox, and the contact damage that has
+/// built up but is less than a whole point yet.
+#[derive(Component, Default)]
+pub struct ContactDamage {
+    touching: HashSet<Entity>,
+    accumulated: f32,
+}
+
+/// Throws an enemy back after it hurts the player. Its steering is ignored
+/// until the timer runs out.
+#[derive(Component)]
+pub struct Knockback {
+    velocity: Vec2,
+    timer: Timer,
+}
+
+impl Knockback {
+    fn new(velocity: Vec2) -> Self {
+        Knockback {
+            velocity,
+            timer: Timer::from_seconds(KNOCKBACK_DURATION, TimerMode::Once),
+        }
+    }
+
+    /// Slows down to a stop over the knockback's duration.
+    pub fn velocity(&self) -> Vec2 {
+        self.velocity * self.timer.percent_left()
+    }
+}
+
 pub struct CombatPlugin;
 
 impl Plugin for CombatPlugin {
     fn build(&self, app: &mut App) {
         app.add_event::<DamageEvent>()
-            .add_startup_system(spawn_health_globe)
-            .add_system(health_globe_update)
-            .add_system(handle_collisions)
-            .add_system(handle_damage);
+            .add_event::<DeathEvent>()
+            .add_system(
+                spawn_health_globe
+                    .run_if(not(any_with_component::<HealthGlobe>()))
+                    .in_schedule(OnEnter(AppState::Playing)),
+            )
+            .add_systems(
+                (
+                    health_globe_update,
+                    handle_collisions,
+                    deal_contact_damage
+                        .after(handle_collisions)
+                        .before(CombatSet::ResolveDamage),
+                    apply_damage.in_set(CombatSet::ResolveDamage),
+                    handle_damage,
+                    update_invulnerability.after(CombatSet::ResolveDamage),
+                    update_knockback,
+                    detect_player_death.after(CombatSet::ResolveDamage),
+                )
+                    .in_set(OnUpdate(AppState::Playing)),
+            );
     }
 }
 
@@ -106,28 +224,167 @@ fn handle_damage(
     }
 }
 
-fn handle_collisions(
+pub fn apply_damage(
     mut commands: Commands,
+    mut damage_events: EventReader<DamageEvent>,
+    mut death_events: EventWriter<DeathEvent>,
+    mut query: Query<(
+        &mut Health,
+        &mut TextureAtlasSprite,
+        Option<&mut Invulnerability>,
+    )>,
+) {
+    for damage_event in damage_events.iter() {
+        let Ok((mut health, mut texture, invulnerability)) = query.get_mut(damage_event.target)
+        else {
+            continue;
+        };
+        if health.current == 0 {
+            continue;
+        }
+        if let Some(mut invulnerability) = invulnerability {
+            if invulnerability.is_active() {
+                continue;
+            }
+            invulnerability.remaining = invulnerability.duration;
+        }
+        health.current = health.current.saturating_sub(damage_event.amount);
+        texture.color = Color::rgba(255., 255., 255., 1.);
+        commands
+            .entity(damage_event.target)
+            .insert(Damage::default());
+        if health.current == 0 {
+            death_events.send(DeathEvent {
+                entity: damage_event.target,
+                source: damage_event.source,
+            });
+        }
+    }
+}
+
+/// Keeps track of which enemies touch the player. The damage itself is dealt
+/// by `deal_contact_damage` for as long as they stay in contact.
+pub fn handle_collisions(
     mut collision_events: EventReader<CollisionEvent>,
-    mut player_query: Query<(Entity, &mut Health, &mut TextureAtlasSprite), With<Player>>,
-    mut query: Query<(Entity, &mut Enemy)>,
+    hitbox_query: Query<&Parent, With<PlayerHitbox>>,
+    mut contact_query: Query<&mut ContactDamage>,
+    enemy_query: Query<(), With<Enemy>>,
 ) {
-    let (player_entity, mut health, mut texture) = player_query.single_mut();
     for collision_event in collision_events.iter() {
-        if let CollisionEvent::Started(e1, e2, _) = collision_event {
-            for (entity, _) in query.iter_mut() {
-                let is_player_entity = e1 == &player_entity || e2 == &player_entity;
-                let is_enemy_entity = e1 == &entity || e2 == &entity;
-                if is_player_entity && is_enemy_entity {
-                    if health.current < 1 {
-                        health.current = 0;
-                    } else {
-                        health.current -= 1;
-                    }
-                    texture.color = Color::rgba(255., 255., 255., 1.);
-                    commands.entity(player_entity).insert(Damage::default());
-                };
+        let (e1, e2, started) = match collision_event {
+            CollisionEvent::Started(e1, e2, _) => (*e1, *e2, true),
+            CollisionEvent::Stopped(e1, e2, _) => (*e1, *e2, false),
+        };
+        for (hitbox_entity, enemy_entity) in [(e1, e2), (e2, e1)] {
+            let Ok(player) = hitbox_query.get(hitbox_entity) else {
+                continue;
+            };
+            let Ok(mut contact) = contact_query.get_mut(player.get()) else {
+                continue;
+            };
+            if !started {
+                contact.touching.remove(&enemy_entity);
+            } else if enemy_query.contains(enemy_entity) {
+                // the first enemy to touch the player hurts straight away
+                if contact.touching.is_empty() {
+                    contact.accumulated = contact.accumulated.max(1.);
+                }
+                contact.touching.insert(enemy_entity);
             }
         }
     }
 }
+
+/// Hurts the player by the damage per second of every enemy touching them, and
+/// knocks those enemies back whenever a hit lands.
+pub fn deal_contact_damage(
+    mut commands: Commands,
+    mut damage_events: EventWriter<DamageEvent>,
+    mut player_query: Query<(Entity, &Transform, &mut ContactDamage, &Invulnerability)>,
+    enemy_query: Query<(&Transform, &Enemy)>,
+    time: Res<Time>,
+) {
+    for (player_entity, player_transform, mut contact, invulnerability) in player_query.iter_mut() {
+        contact
+            .touching
+            .retain(|enemy_entity| enemy_query.contains(*enemy_entity));
+        let Some(&source) = contact.touching.iter().next() else {
+            contact.accumulated = 0.;
+            continue;
+        };
+        if invulnerability.is_active() {
+            continue;
+        }
+        let damage_per_second: f32 = enemy_query
+            .iter_many(&contact.touching)
+            .map(|(_, enemy)| enemy.contact_damage)
+            .sum();
+        contact.accumulated += damage_per_second * time.delta_seconds();
+        if contact.accumulated < 1. {
+            continue;
+        }
+        let amount = contact.accumulated.floor();
+        contact.accumulated -= amount;
+        damage_events.send(DamageEvent {
+            source,
+            target: player_entity,
+            amount: amount as usize,
+            damage_type: DamageType::Physical,
+            crit: false,
+        });
+        for enemy_entity in contact.touching.iter() {
+            let Ok((transform, _)) = enemy_query.get(*enemy_entity) else {
+                continue;
+            };
+            let away = (transform.translation - player_transform.translation)
+                .truncate()
+                .normalize_or_zero();
+            commands
+                .entity(*enemy_entity)
+                .insert(Knockback::new(away * KNOCKBACK_SPEED));
+        }
+    }
+}
+
+fn update_invulnerability(
+    mut query: Query<(&mut Invulnerability, &mut Visibility)>,
+    time: Res<Time>,
+) {
+    for (mut invulnerability, mut visibility) in query.iter_mut() {
+        if !invulnerability.is_active() {
+            continue;
+        }
+        invulnerability.remaining = (invulnerability.remaining - time.delta_seconds()).max(0.);
+        let blink_off = (invulnerability.remaining / BLINK_INTERVAL) as u32 % 2 == 1;
+        *visibility = if invulnerability.is_active() && blink_off {
+            Visibility::Hidden
+        } else {
+            Visibility::Inherited
+        };
+    }
+}
+
+fn update_knockback(
+    mut commands: Commands,
+    mut query: Query<(Entity, &mut Knockback)>,
+    time: Res<Time>,
+) {
+    for (entity, mut knockback) in query.iter_mut() {
+        knockback.timer.tick(time.delta());
+        if knockback.timer.finished() {
+            commands.entity(entity).remove::<Knockback>();
+        }
+    }
+}
+
+fn detect_player_death(
+    mut death_events: EventReader<DeathEvent>,
+    player_query: Query<(), With<Player>>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    for death_event in death_events.iter() {
+        if player_query.contains(death_event.entity) {
+            next_state.set(AppState::GameOver);
+        }
+    }
+}
@@ -0,0 +1,252 @@
+use bevy::prelude::*;
+
+use crate::{
+    game_state::{despawn_screen, settings_panel, spawn_button, AppState},
+    utils::arg_value,
+};
+
+/// Stick deflection below this is treated as the stick being let go.
+pub const STICK_DEAD_ZONE: f32 = 0.2;
+
+/// Gamepad buttons for the spellbook slots, in slot order.
+pub const SPELL_BUTTONS: [GamepadButtonType; 4] = [
+    GamepadButtonType::West,
+    GamepadButtonType::North,
+    GamepadButtonType::East,
+    GamepadButtonType::RightTrigger,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MovementScheme {
+    /// Right click somewhere to walk there.
+    ClickToMove,
+    /// WASD or the arrow keys.
+    Keyboard,
+    /// The left stick of the first gamepad.
+    Gamepad,
+}
+
+impl MovementScheme {
+    const ALL: [MovementScheme; 3] = [
+        MovementScheme::ClickToMove,
+        MovementScheme::Keyboard,
+        MovementScheme::Gamepad,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            MovementScheme::ClickToMove => "Click to move",
+            MovementScheme::Keyboard => "WASD",
+            MovementScheme::Gamepad => "Gamepad",
+        }
+    }
+
+    /// Keys that cast the spellbook slots. Q W E R overlap WASD, so walking
+    /// with the keyboard moves spells to the number row.
+    pub fn spell_keys(&self) -> [KeyCode; 4] {
+        match self {
+            MovementScheme::Keyboard => {
+                [KeyCode::Key1, KeyCode::Key2, KeyCode::Key3, KeyCode::Key4]
+            }
+            MovementScheme::ClickToMove | MovementScheme::Gamepad => {
+                [KeyCode::Q, KeyCode::W, KeyCode::E, KeyCode::R]
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AimMode {
+    Cursor,
+    /// The right stick, or the nearest enemy while the stick is let go.
+    RightStick,
+    /// Always the nearest enemy.
+    Auto,
+}
+
+impl AimMode {
+    const ALL: [AimMode; 3] = [AimMode::Cursor, AimMode::RightStick, AimMode::Auto];
+
+    fn name(&self) -> &'static str {
+        match self {
+            AimMode::Cursor => "Cursor",
+            AimMode::RightStick => "Right stick",
+            AimMode::Auto => "Auto",
+        }
+    }
+}
+
+/// The option after `current` in `all`, wrapping around at the end.
+fn next<T: Copy + PartialEq>(all: &[T], current: T) -> T {
+    let index = all.iter().position(|item| *item == current).unwrap_or(0);
+    all[(index + 1) % all.len()]
+}
+
+#[derive(Resource)]
+pub struct Controls {
+    pub movement: MovementScheme,
+    pub aim: AimMode,
+}
+
+impl Controls {
+    /// Reads `--controls click|wasd|gamepad` and `--aim cursor|stick|auto` as
+    /// the starting choice, which can be changed from the main menu. Gamepad
+    /// players aim with the right stick unless told otherwise, everyone else
+    /// with the cursor.
+    fn from_args() -> Self {
+        let movement = match arg_value("--controls").as_deref() {
+            None | Some("click") => MovementScheme::ClickToMove,
+            Some("wasd") => MovementScheme::Keyboard,
+            Some("gamepad") => MovementScheme::Gamepad,
+            Some(value) => {
+                warn!("unknown --controls value {value:?}, using click to move");
+                MovementScheme::ClickToMove
+            }
+        };
+        let aim = match arg_value("--aim").as_deref() {
+            None if movement == MovementScheme::Gamepad => AimMode::RightStick,
+            None | Some("cursor") => AimMode::Cursor,
+            Some("stick") => AimMode::RightStick,
+            Some("auto") => AimMode::Auto,
+            Some(value) => {
+                warn!("unknown --aim value {value:?}, using the cursor");
+                AimMode::Cursor
+            }
+        };
+        Controls { movement, aim }
+    }
+}
+
+/// The first connected gamepad, if any.
+pub fn first_gamepad(gamepads: &Gamepads) -> Option<Gamepad> {
+    gamepads.iter().next()
+}
+
+/// Whether `button` was just pressed on any connected gamepad. Menus listen to
+/// all of them, not only the one that plays.
+pub fn gamepad_just_pressed(
+    gamepads: &Gamepads,
+    gamepad_input: &Input<GamepadButton>,
+    button: GamepadButtonType,
+) -> bool {
+    gamepads
+        .iter()
+        .any(|gamepad| gamepad_input.just_pressed(GamepadButton::new(gamepad, button)))
+}
+
+/// Position of one of `gamepad`'s sticks, or `None` inside the dead zone.
+pub fn stick(
+    axes: &Axis<GamepadAxis>,
+    gamepad: Gamepad,
+    x: GamepadAxisType,
+    y: GamepadAxisType,
+) -> Option<Vec2> {
+    let position = Vec2::new(
+        axes.get(GamepadAxis::new(gamepad, x))?,
+        axes.get(GamepadAxis::new(gamepad, y))?,
+    );
+    (position.length() > STICK_DEAD_ZONE).then_some(position)
+}
+
+/// The main menu's buttons for choosing the controls.
+#[derive(Component)]
+struct ControlsMenu;
+
+#[derive(Component, Clone, Copy)]
+enum ControlsButton {
+    Movement,
+    Aim,
+}
+
+impl ControlsButton {
+    /// Key that does the same as clicking the button.
+    fn key(&self) -> KeyCode {
+        match self {
+            ControlsButton::Movement => KeyCode::C,
+            ControlsButton::Aim => KeyCode::A,
+        }
+    }
+
+    /// Gamepad button that does the same as clicking the button.
+    fn gamepad_button(&self) -> GamepadButtonType {
+        match self {
+            ControlsButton::Movement => GamepadButtonType::West,
+            ControlsButton::Aim => GamepadButtonType::North,
+        }
+    }
+
+    fn label(&self, controls: &Controls) -> String {
+        match self {
+            ControlsButton::Movement => {
+                format!("Controls: {} (C / X)", controls.movement.name())
+            }
+            ControlsButton::Aim => format!("Aim: {} (A / Y)", controls.aim.name()),
+        }
+    }
+}
+
+pub struct ControlsPlugin;
+
+impl Plugin for ControlsPlugin {
+    fn build(&self, app: &mut App) {
+        let controls = Controls::from_args();
+        info!("controls: {:?}, aim: {:?}", controls.movement, controls.aim);
+        app.insert_resource(controls)
+            .add_system(spawn_controls_menu.in_schedule(OnEnter(AppState::MainMenu)))
+            .add_system(despawn_screen::<ControlsMenu>.in_schedule(OnExit(AppState::MainMenu)))
+            .add_system(change_controls.in_set(OnUpdate(AppState::MainMenu)));
+    }
+}
+
+fn spawn_controls_menu(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    controls: Res<Controls>,
+) {
+    let font = asset_server.load("fonts/DMSans-Regular.ttf");
+    commands
+        .spawn((settings_panel(), ControlsMenu))
+        .with_children(|parent| {
+            for button in [ControlsButton::Movement, ControlsButton::Aim] {
+                spawn_button(parent, &font, button.label(&controls), button);
+            }
+        });
+}
+
+/// Steps through the options of a button when it is clicked or its key is
+/// pressed.
+fn change_controls(
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepad_input: Res<Input<GamepadButton>>,
+    gamepads: Res<Gamepads>,
+    button_query: Query<(&Interaction, &ControlsButton), Changed<Interaction>>,
+    mut text_query: Query<(&mut Text, &ControlsButton)>,
+    mut controls: ResMut<Controls>,
+) {
+    let pressed = [ControlsButton::Movement, ControlsButton::Aim]
+        .into_iter()
+        .filter(|button| {
+            keyboard_input.just_pressed(button.key())
+                || gamepad_just_pressed(&gamepads, &gamepad_input, button.gamepad_button())
+        });
+    let clicked = button_query
+        .iter()
+        .filter(|(interaction, _)| **interaction == Interaction::Clicked)
+        .map(|(_, button)| *button);
+    let mut changed = false;
+    for button in pressed.chain(clicked) {
+        match button {
+            ControlsButton::Movement => {
+                controls.movement = next(&MovementScheme::ALL, controls.movement);
+            }
+            ControlsButton::Aim => controls.aim = next(&AimMode::ALL, controls.aim),
+        }
+        changed = true;
+    }
+    if !changed {
+        return;
+    }
+    for (mut text, button) in text_query.iter_mut() {
+        text.sections[0].value = button.label(&controls);
+    }
+}
@@ -0,0 +1,167 @@
+use bevy::prelude::*;
+use rand::Rng;
+
+use crate::{
+    combat::{CombatSet, DamageEvent, DamageType},
+    enemy::Enemy,
+    game_state::AppState,
+};
+
+/// Seconds a number stays on screen.
+const LIFETIME: f32 = 0.7;
+const FLOAT_SPEED: f32 = 40.;
+/// Numbers on screen at once. Hits beyond that show nothing until some fade.
+const MAX_DAMAGE_NUMBERS: usize = 200;
+const FONT_SIZE: f32 = 16.;
+const CRIT_FONT_SIZE: f32 = 24.;
+/// Numbers start up to this far to either side of the target, so a burst of
+/// hits doesn't stack into one.
+const HORIZONTAL_JITTER: f32 = 8.;
+
+#[derive(Component)]
+struct DamageNumber {
+    age: f32,
+    color: Color,
+}
+
+/// Hidden damage numbers ready to be shown again, so heavy combat doesn't
+/// spawn and despawn text every frame.
+#[derive(Resource, Default)]
+struct DamageNumberPool {
+    free: Vec<Entity>,
+    /// Numbers spawned so far, shown or not.
+    total: usize,
+    font: Handle<Font>,
+}
+
+fn color(damage_type: DamageType, crit: bool) -> Color {
+    if crit {
+        return Color::rgb(1., 0.9, 0.1);
+    }
+    match damage_type {
+        DamageType::Physical => Color::WHITE,
+        DamageType::Fire => Color::rgb(1., 0.55, 0.1),
+        DamageType::Arcane => Color::rgb(0.7, 0.45, 1.),
+        DamageType::Explosion => Color::rgb(1., 0.25, 0.2),
+    }
+}
+
+pub struct DamageNumberPlugin;
+
+impl Plugin for DamageNumberPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DamageNumberPool>()
+            .add_startup_system(load_damage_number_font)
+            // numbers despawned with the previous run can't be reused
+            .add_system(reset_pool.in_schedule(OnExit(AppState::GameOver)))
+            .add_systems(
+                (
+                    show_damage_numbers.after(CombatSet::ResolveDamage),
+                    animate_damage_numbers,
+                )
+                    .in_set(OnUpdate(AppState::Playing)),
+            );
+    }
+}
+
+fn load_damage_number_font(mut pool: ResMut<DamageNumberPool>, asset_server: Res<AssetServer>) {
+    pool.font = asset_server.load("fonts/DMSans-Regular.ttf");
+}
+
+fn reset_pool(mut pool: ResMut<DamageNumberPool>) {
+    pool.free.clear();
+    pool.total = 0;
+}
+
+fn show_damage_numbers(
+    mut commands: Commands,
+    mut damage_events: EventReader<DamageEvent>,
+    mut pool: ResMut<DamageNumberPool>,
+    enemy_query: Query<&Transform, With<Enemy>>,
+    mut query: Query<
+        (
+            &mut DamageNumber,
+            &mut Text,
+            &mut Transform,
+            &mut Visibility,
+        ),
+        Without<Enemy>,
+    >,
+) {
+    for damage_event in damage_events.iter() {
+        let Ok(enemy_transform) = enemy_query.get(damage_event.target) else {
+            continue;
+        };
+        let color = color(damage_event.damage_type, damage_event.crit);
+        let mut value = damage_event.amount.to_string();
+        if damage_event.crit {
+            value.push('!');
+        }
+        let style = TextStyle {
+            font: pool.font.clone(),
+            font_size: if damage_event.crit {
+                CRIT_FONT_SIZE
+            } else {
+                FONT_SIZE
+            },
+            color,
+        };
+        // cosmetic only, so it doesn't draw from the game's rng
+        let jitter = rand::thread_rng().gen_range(-HORIZONTAL_JITTER..HORIZONTAL_JITTER);
+        let translation = enemy_transform.translation.truncate() + Vec2::new(jitter, 0.);
+        let transform = Transform::from_translation(translation.extend(10.));
+
+        if let Some(entity) = pool.free.pop() {
+            let Ok((mut number, mut text, mut number_transform, mut visibility)) =
+                query.get_mut(entity)
+            else {
+                continue;
+            };
+            *number = DamageNumber { age: 0., color };
+            *text = Text::from_section(value, style);
+            *number_transform = transform;
+            *visibility = Visibility::Inherited;
+        } else if pool.total < MAX_DAMAGE_NUMBERS {
+            pool.total += 1;
+            commands.spawn((
+                Text2dBundle {
+                    text: Text::from_section(value, style),
+                    transform,
+                    ..default()
+                },
+                DamageNumber { age: 0., color },
+                Name::from("Damage Number"),
+            ));
+        }
+    }
+}
+
+/// Floats the numbers up while they fade out, then hides them and puts them
+/// back in the pool.
+fn animate_damage_numbers(
+    mut pool: ResMut<DamageNumberPool>,
+    mut query: Query<(
+        Entity,
+        &mut DamageNumber,
+        &mut Text,
+        &mut Transform,
+        &mut Visibility,
+    )>,
+    time: Res<Time>,
+) {
+    let delta = time.delta_seconds();
+    for (entity, mut number, mut text, mut transform, mut visibility) in query.iter_mut() {
+        if *visibility == Visibility::Hidden {
+            continue;
+        }
+        number.age += delta;
+        if number.age >= LIFETIME {
+            *visibility = Visibility::Hidden;
+            pool.free.push(entity);
+            continue;
+        }
+        transform.translation.y += FLOAT_SPEED * delta;
+        let progress = number.age / LIFETIME;
+        text.sections[0].style.color = number.color.with_a(1. - progress * progress);
+    }
+}
@@ -0,0 +1,421 @@
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+use bevy_rapier2d::{
+    prelude::*,
+    rapier::{
+        math::Isometry,
+        parry::query::{self, Contact},
+    },
+};
+
+use crate::{
+    combat::{ENEMY_PROJECTILE_GROUP, PLAYER_SPELL_GROUP, WALL_GROUP},
+    dungeon_generator::generate,
+    enemy::{Enemy, EnemyProjectile},
+    game_state::AppState,
+    player::Player,
+    rng::{GameRng, RngSet},
+    spell::Spell,
+    sprite_sheets::{tile_index, SpriteSheetsMaps},
+    utils::arg_value,
+};
+
+/// World size of one map cell.
+pub const TILE_SIZE: f32 = 32.;
+/// Overlaps shallower than this are left alone, they are only touching.
+const PUSH_OUT_TOLERANCE: f32 = -0.01;
+/// Walls a body can be pushed out of in one frame.
+const MAX_PUSH_OUTS: usize = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tile {
+    /// Outside the dungeon. Nothing is drawn and nothing can get there.
+    Empty,
+    Floor,
+    Sand,
+    Wall,
+}
+
+impl Tile {
+    pub fn is_walkable(&self) -> bool {
+        matches!(self, Tile::Floor | Tile::Sand)
+    }
+}
+
+/// A dungeon level as described by a `*.map.txt` file under `assets/dungeons/`.
+///
+/// Each character is one cell: `#` is a wall, `.` a floor, `,` a sandy floor,
+/// `@` the floor the player starts on, `e` a floor enemies can spawn on and a
+/// space is empty. Row 0 is the top of the map.
+#[derive(TypeUuid)]
+#[uuid = "3f9a6d21-4c8e-47b5-9e1a-8d2c5b7f0e63"]
+pub struct DungeonMap {
+    pub width: usize,
+    pub height: usize,
+    tiles: Vec<Tile>,
+    pub spawn: UVec2,
+    /// Cells the wave system spawns enemies on.
+    pub enemy_spawns: Vec<UVec2>,
+}
+
+impl DungeonMap {
+    pub fn new(
+        width: usize,
+        height: usize,
+        tiles: Vec<Tile>,
+        spawn: UVec2,
+        enemy_spawns: Vec<UVec2>,
+    ) -> Self {
+        DungeonMap {
+            width,
+            height,
+            tiles,
+            spawn,
+            enemy_spawns,
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, bevy::asset::Error> {
+        let lines: Vec<&str> = text.lines().collect();
+        let width = lines.iter().map(|line| line.len()).max().unwrap_or(0);
+        let height = lines.len();
+        let mut tiles = vec![Tile::Empty; width * height];
+        let mut spawn = None;
+        let mut enemy_spawns = Vec::new();
+        for (y, line) in lines.iter().enumerate() {
+            for (x, character) in line.chars().enumerate() {
+                tiles[y * width + x] = match character {
+                    ' ' => Tile::Empty,
+                    '.' => Tile::Floor,
+                    ',' => Tile::Sand,
+                    '#' => Tile::Wall,
+                    '@' => {
+                        spawn = Some(UVec2::new(x as u32, y as u32));
+                        Tile::Floor
+                    }
+                    'e' => {
+                        enemy_spawns.push(UVec2::new(x as u32, y as u32));
+                        Tile::Floor
+                    }
+                    _ => {
+                        return Err(bevy::asset::Error::msg(format!(
+                            "unknown map tile {character:?} at {x},{y}"
+                        )))
+                    }
+                };
+            }
+        }
+        let spawn = spawn.ok_or_else(|| bevy::asset::Error::msg("map has no `@` spawn"))?;
+        Ok(DungeonMap::new(width, height, tiles, spawn, enemy_spawns))
+    }
+
+    /// The tile at `cell`, or `Tile::Empty` outside the map.
+    pub fn get(&self, cell: IVec2) -> Tile {
+        if cell.x < 0
+            || cell.y < 0
+            || cell.x as usize >= self.width
+            || cell.y as usize >= self.height
+        {
+            return Tile::Empty;
+        }
+        self.tiles[cell.y as usize * self.width + cell.x as usize]
+    }
+
+    /// World position of the center of `cell`.
+    pub fn cell_center(cell: IVec2) -> Vec2 {
+        Vec2::new(cell.x as f32, -cell.y as f32) * TILE_SIZE
+    }
+
+    /// The cell `point` lies in.
+    pub fn cell_at(point: Vec2) -> IVec2 {
+        (Vec2::new(point.x, -point.y) / TILE_SIZE)
+            .round()
+            .as_ivec2()
+    }
+}
+
+#[derive(Default)]
+pub struct DungeonMapLoader;
+
+impl AssetLoader for DungeonMapLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let map = DungeonMap::parse(std::str::from_utf8(bytes)?)?;
+            load_context.set_default_asset(LoadedAsset::new(map));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["map.txt"]
+    }
+}
+
+/// Where levels come from. `--map <path>` plays a map file from `assets/`,
+/// otherwise every run generates a level from the seed.
+#[derive(Resource)]
+pub enum DungeonSource {
+    File(Handle<DungeonMap>),
+    Generated,
+}
+
+/// The map of the level being played.
+#[derive(Resource)]
+pub struct CurrentDungeon(pub Handle<DungeonMap>);
+
+/// Root of every tile and wall collider of the level.
+#[derive(Component)]
+pub struct Dungeon;
+
+#[derive(Component)]
+pub struct Wall;
+
+pub struct DungeonPlugin;
+
+impl Plugin for DungeonPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<DungeonMap>()
+            .init_asset_loader::<DungeonMapLoader>()
+            .add_startup_system(load_dungeon)
+            .add_system(
+                spawn_dungeon
+                    .run_if(not(any_with_component::<Dungeon>()))
+                    .in_set(RngSet::Dungeon)
+                    .in_set(OnUpdate(AppState::Playing)),
+            )
+            .add_system(despawn_projectiles_on_walls.in_set(OnUpdate(AppState::Playing)))
+            .add_system(
+                // after every system that sets a velocity, before rapier moves anything
+                slide_along_walls
+                    .run_if(in_state(AppState::Playing))
+                    .in_base_set(CoreSet::PostUpdate)
+                    .before(PhysicsSet::SyncBackend),
+            );
+    }
+}
+
+fn load_dungeon(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let source = match arg_value("--map") {
+        Some(path) => DungeonSource::File(asset_server.load(path)),
+        None => DungeonSource::Generated,
+    };
+    commands.insert_resource(source);
+}
+
+/// Builds the level once its map is ready and moves the player onto the spawn
+/// tile.
+fn spawn_dungeon(
+    mut commands: Commands,
+    source: Res<DungeonSource>,
+    mut dungeon_maps: ResMut<Assets<DungeonMap>>,
+    mut rng: ResMut<GameRng>,
+    sprite_sheets_maps: Res<SpriteSheetsMaps>,
+    mut player_query: Query<(&mut Player, &mut Transform)>,
+) {
+    let Ok((mut player, mut player_transform)) = player_query.get_single_mut() else {
+        return;
+    };
+    let handle = match &*source {
+        DungeonSource::File(handle) => handle.clone(),
+        DungeonSource::Generated => dungeon_maps.add(generate(&mut **rng)),
+    };
+    let Some(map) = dungeon_maps.get(&handle) else {
+        return;
+    };
+    commands.insert_resource(CurrentDungeon(handle.clone()));
+    let spawn = DungeonMap::cell_center(map.spawn.as_ivec2()).extend(0.);
+    player_transform.translation = spawn;
+    player.destination = spawn;
+
+    commands
+        .spawn((SpatialBundle::default(), Dungeon, Name::from("Dungeon")))
+        .with_children(|parent| {
+            for y in 0..map.height as i32 {
+                for x in 0..map.width as i32 {
+                    let cell = IVec2::new(x, y);
+                    let (index, z) = match map.get(cell) {
+                        Tile::Empty => continue,
+                        Tile::Floor => (floor_variant(cell), -1.),
+                        Tile::Sand => (tile_index(5, 1 + ((x + y * 3) % 4) as usize), -1.),
+                        Tile::Wall => (tile_index(4, 5), -0.9),
+                    };
+                    parent.spawn(SpriteSheetBundle {
+                        texture_atlas: sprite_sheets_maps.characters_atlas.clone(),
+                        sprite: TextureAtlasSprite {
+                            index,
+                            custom_size: Some(Vec2::splat(TILE_SIZE)),
+                            ..default()
+                        },
+                        transform: Transform::from_translation(
+                            DungeonMap::cell_center(cell).extend(z),
+                        ),
+                        ..default()
+                    });
+                }
+            }
+
+            // one collider per horizontal run of walls instead of one per tile
+            for y in 0..map.height as i32 {
+                let mut x = 0;
+                while x < map.width as i32 {
+                    if map.get(IVec2::new(x, y)) != Tile::Wall {
+                        x += 1;
+                        continue;
+                    }
+                    let start = x;
+                    while map.get(IVec2::new(x, y)) == Tile::Wall {
+                        x += 1;
+                    }
+                    let length = (x - start) as f32;
+                    let center = (DungeonMap::cell_center(IVec2::new(start, y))
+                        + DungeonMap::cell_center(IVec2::new(x - 1, y)))
+                        / 2.;
+                    parent.spawn((
+                        RigidBody::Fixed,
+                        Collider::cuboid(length * TILE_SIZE / 2., TILE_SIZE / 2.),
+                        CollisionGroups::new(
+                            WALL_GROUP,
+                            PLAYER_SPELL_GROUP | ENEMY_PROJECTILE_GROUP,
+                        ),
+                        ActiveEvents::COLLISION_EVENTS,
+                        ActiveCollisionTypes::default() | ActiveCollisionTypes::KINEMATIC_STATIC,
+                        TransformBundle::from_transform(Transform::from_translation(
+                            center.extend(0.),
+                        )),
+                        Wall,
+                    ));
+                }
+            }
+        });
+}
+
+/// Mostly plain floor with the odd cracked tile, picked by position so the
+/// level looks the same every run.
+fn floor_variant(cell: IVec2) -> usize {
+    match (cell.x * 7 + cell.y * 13) % 11 {
+        0 => tile_index(2, 1),
+        1 => tile_index(3, 1),
+        _ => tile_index(1, 1),
+    }
+}
+
+type ProjectileFilter = Or<(With<Spell>, With<EnemyProjectile>)>;
+
+fn despawn_projectiles_on_walls(
+    mut commands: Commands,
+    mut collision_events: EventReader<CollisionEvent>,
+    wall_query: Query<(), With<Wall>>,
+    projectile_query: Query<(), ProjectileFilter>,
+) {
+    for collision_event in collision_events.iter() {
+        if let CollisionEvent::Started(e1, e2, _) = collision_event {
+            for (wall_entity, projectile_entity) in [(*e1, *e2), (*e2, *e1)] {
+                if wall_query.contains(wall_entity) && projectile_query.contains(projectile_entity)
+                {
+                    commands.entity(projectile_entity).despawn();
+                }
+            }
+        }
+    }
+}
+
+type WalkerFilter = Or<(With<Player>, With<Enemy>)>;
+
+/// Kinematic bodies go straight through fixed colliders, so the player and
+/// enemies have the part of their velocity that points into a wall removed
+/// before the physics step. Bodies that already overlap a wall, after a
+/// knockback or when spawned against one, are first pushed back out.
+fn slide_along_walls(
+    rapier_context: Res<RapierContext>,
+    mut query: Query<(&mut Transform, &Collider, &mut Velocity), WalkerFilter>,
+    wall_query: Query<(&GlobalTransform, &Collider), With<Wall>>,
+    time: Res<Time>,
+) {
+    let delta = time.delta_seconds();
+    for (mut transform, collider, mut velocity) in query.iter_mut() {
+        // one wall at a time, as a body straddling two wall runs would
+        // otherwise be pushed out by both
+        for _ in 0..MAX_PUSH_OUTS {
+            let Some(push) = deepest_penetration(
+                transform.translation.truncate(),
+                collider,
+                &rapier_context,
+                &wall_query,
+            ) else {
+                break;
+            };
+            transform.translation += push.extend(0.);
+        }
+        // a second pass handles corners, where two walls are hit at once
+        for _ in 0..2 {
+            if velocity.linvel == Vec2::ZERO {
+                break;
+            }
+            let Some((_, toi)) = rapier_context.cast_shape(
+                transform.translation.truncate(),
+                0.,
+                velocity.linvel,
+                collider,
+                delta,
+                QueryFilter::only_fixed(),
+            ) else {
+                break;
+            };
+            if toi.status == TOIStatus::Penetrating {
+                break;
+            }
+            let normal = toi.normal1;
+            let into_wall = velocity.linvel.dot(normal);
+            if into_wall < 0. {
+                velocity.linvel -= normal * into_wall;
+            }
+        }
+    }
+}
+
+/// The shortest move that gets `collider` at `position` out of the wall it
+/// overlaps the most, if it overlaps any.
+fn deepest_penetration(
+    position: Vec2,
+    collider: &Collider,
+    rapier_context: &RapierContext,
+    wall_query: &Query<(&GlobalTransform, &Collider), With<Wall>>,
+) -> Option<Vec2> {
+    let isometry = |translation: Vec2| Isometry::translation(translation.x, translation.y);
+    let mut deepest: Option<Contact> = None;
+    rapier_context.intersections_with_shape(
+        position,
+        0.,
+        collider,
+        QueryFilter::only_fixed(),
+        |wall_entity| {
+            let Ok((wall_transform, wall_collider)) = wall_query.get(wall_entity) else {
+                return true;
+            };
+            let contact = query::contact(
+                &isometry(position),
+                &*collider.raw,
+                &isometry(wall_transform.translation().truncate()),
+                &*wall_collider.raw,
+                0.,
+            );
+            if let Ok(Some(contact)) = contact {
+                if contact.dist < PUSH_OUT_TOLERANCE
+                    && deepest.is_none_or(|deepest| contact.dist < deepest.dist)
+                {
+                    deepest = Some(contact);
+                }
+            }
+            true
+        },
+    );
+    deepest.map(|contact| Vec2::new(contact.normal1.x, contact.normal1.y) * contact.dist)
+}
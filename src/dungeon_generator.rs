@@ -0,0 +1,290 @@
+use bevy::prelude::*;
+use rand::Rng;
+
+use crate::dungeon::{DungeonMap, Tile};
+
+const MAP_WIDTH: i32 = 72;
+const MAP_HEIGHT: i32 = 54;
+const ROOM_ATTEMPTS: usize = 200;
+const MAX_ROOMS: usize = 12;
+const MIN_ROOM_SIZE: i32 = 6;
+const MAX_ROOM_SIZE: i32 = 14;
+/// Empty cells kept between two rooms so their walls don't merge.
+const ROOM_GAP: i32 = 3;
+/// Corridors are this many floor tiles wide.
+const CORRIDOR_WIDTH: i32 = 3;
+/// Chance of an extra corridor between two rooms that are already connected,
+/// so the level has loops instead of only dead ends.
+const LOOP_CHANCE: f64 = 0.3;
+
+/// Floor cells from `min` to `max`, both inclusive.
+#[derive(Clone, Copy, Debug)]
+struct Room {
+    min: IVec2,
+    max: IVec2,
+}
+
+impl Room {
+    fn center(&self) -> IVec2 {
+        (self.min + self.max) / 2
+    }
+
+    fn overlaps(&self, other: &Room, gap: i32) -> bool {
+        self.min.x - gap <= other.max.x
+            && other.min.x - gap <= self.max.x
+            && self.min.y - gap <= other.max.y
+            && other.min.y - gap <= self.max.y
+    }
+}
+
+/// Generates a dungeon of connected rooms. The first room is where the player
+/// starts, every other room gets enemy spawn points.
+pub fn generate(rng: &mut impl Rng) -> DungeonMap {
+    let rooms = place_rooms(rng);
+    build_map(&rooms, rng)
+}
+
+fn place_rooms(rng: &mut impl Rng) -> Vec<Room> {
+    let mut rooms: Vec<Room> = Vec::new();
+    for _ in 0..ROOM_ATTEMPTS {
+        if rooms.len() == MAX_ROOMS {
+            break;
+        }
+        let size = IVec2::new(
+            rng.gen_range(MIN_ROOM_SIZE..=MAX_ROOM_SIZE),
+            rng.gen_range(MIN_ROOM_SIZE..=MAX_ROOM_SIZE * 3 / 4),
+        );
+        // keep a border for the outer walls
+        let min = IVec2::new(
+            rng.gen_range(1..MAP_WIDTH - size.x - 1),
+            rng.gen_range(1..MAP_HEIGHT - size.y - 1),
+        );
+        let room = Room {
+            min,
+            max: min + size - 1,
+        };
+        if rooms.iter().all(|other| !room.overlaps(other, ROOM_GAP)) {
+            rooms.push(room);
+        }
+    }
+    rooms
+}
+
+fn build_map(rooms: &[Room], rng: &mut impl Rng) -> DungeonMap {
+    let mut grid = Grid::new(MAP_WIDTH, MAP_HEIGHT);
+    for (index, room) in rooms.iter().enumerate() {
+        let tile = if index > 0 && rng.gen_bool(0.25) {
+            Tile::Sand
+        } else {
+            Tile::Floor
+        };
+        grid.fill(room.min, room.max, tile);
+    }
+
+    // Joining every room to the closest one placed before it gives a spanning
+    // tree, so everything is reachable from the first room.
+    for (index, room) in rooms.iter().enumerate().skip(1) {
+        let closest = rooms[..index]
+            .iter()
+            .min_by_key(|other| {
+                let offset = (other.center() - room.center()).abs();
+                offset.x + offset.y
+            })
+            .unwrap();
+        grid.corridor(room.center(), closest.center(), rng);
+        if index > 1 && rng.gen_bool(LOOP_CHANCE) {
+            let other = rooms[rng.gen_range(0..index)];
+            grid.corridor(room.center(), other.center(), rng);
+        }
+    }
+
+    // pillars in the corners of the big rooms, away from the corridor ends
+    for room in rooms.iter().skip(1) {
+        let size = room.max - room.min + 1;
+        if size.x >= 10 && size.y >= 8 {
+            for corner in [
+                room.min + 2,
+                IVec2::new(room.max.x - 2, room.min.y + 2),
+                IVec2::new(room.min.x + 2, room.max.y - 2),
+                room.max - 2,
+            ] {
+                grid.set(corner, Tile::Wall);
+            }
+        }
+    }
+
+    grid.surround_with_walls();
+
+    let spawn = rooms[0].center().as_uvec2();
+    let enemy_spawns = rooms
+        .iter()
+        .skip(1)
+        .flat_map(|room| {
+            let inset = room.min + 1;
+            let far = room.max - 1;
+            [
+                room.center(),
+                inset,
+                IVec2::new(far.x, inset.y),
+                IVec2::new(inset.x, far.y),
+                far,
+            ]
+        })
+        .filter(|cell| grid.get(*cell).is_walkable())
+        .map(|cell| cell.as_uvec2())
+        .collect();
+    DungeonMap::new(
+        MAP_WIDTH as usize,
+        MAP_HEIGHT as usize,
+        grid.tiles,
+        spawn,
+        enemy_spawns,
+    )
+}
+
+struct Grid {
+    width: i32,
+    height: i32,
+    tiles: Vec<Tile>,
+}
+
+impl Grid {
+    fn new(width: i32, height: i32) -> Self {
+        Grid {
+            width,
+            height,
+            tiles: vec![Tile::Empty; (width * height) as usize],
+        }
+    }
+
+    fn contains(&self, cell: IVec2) -> bool {
+        cell.x >= 0 && cell.y >= 0 && cell.x < self.width && cell.y < self.height
+    }
+
+    fn get(&self, cell: IVec2) -> Tile {
+        if !self.contains(cell) {
+            return Tile::Empty;
+        }
+        self.tiles[(cell.y * self.width + cell.x) as usize]
+    }
+
+    fn set(&mut self, cell: IVec2, tile: Tile) {
+        if self.contains(cell) {
+            self.tiles[(cell.y * self.width + cell.x) as usize] = tile;
+        }
+    }
+
+    fn fill(&mut self, min: IVec2, max: IVec2, tile: Tile) {
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                self.set(IVec2::new(x, y), tile);
+            }
+        }
+    }
+
+    /// An L-shaped corridor between two cells. Floors that are already there,
+    /// like a sandy room, are kept.
+    fn corridor(&mut self, from: IVec2, to: IVec2, rng: &mut impl Rng) {
+        let corner = if rng.gen_bool(0.5) {
+            IVec2::new(to.x, from.y)
+        } else {
+            IVec2::new(from.x, to.y)
+        };
+        let half = CORRIDOR_WIDTH / 2;
+        for (start, end) in [(from, corner), (corner, to)] {
+            let min = start.min(end) - half;
+            let max = start.max(end) + half;
+            for y in min.y..=max.y {
+                for x in min.x..=max.x {
+                    let cell = IVec2::new(x, y);
+                    // stay off the border so the outer walls always fit
+                    let inside = x > 0 && y > 0 && x < self.width - 1 && y < self.height - 1;
+                    if inside && !self.get(cell).is_walkable() {
+                        self.set(cell, Tile::Floor);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Turns every empty cell next to a floor into a wall.
+    fn surround_with_walls(&mut self) {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let cell = IVec2::new(x, y);
+                if self.get(cell) != Tile::Empty {
+                    continue;
+                }
+                let next_to_floor = (-1..=1)
+                    .flat_map(|dy| (-1..=1).map(move |dx| IVec2::new(dx, dy)))
+                    .any(|offset| self.get(cell + offset).is_walkable());
+                if next_to_floor {
+                    self.set(cell, Tile::Wall);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashSet, VecDeque};
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    /// Every walkable cell that can be walked to from the spawn.
+    fn reachable(map: &DungeonMap) -> HashSet<IVec2> {
+        let start = map.spawn.as_ivec2();
+        let mut visited = HashSet::from([start]);
+        let mut queue = VecDeque::from([start]);
+        while let Some(cell) = queue.pop_front() {
+            for offset in [IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y] {
+                let next = cell + offset;
+                if map.get(next).is_walkable() && visited.insert(next) {
+                    queue.push_back(next);
+                }
+            }
+        }
+        visited
+    }
+
+    #[test]
+    fn every_room_is_reachable_from_the_spawn() {
+        for seed in 0..100 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let rooms = place_rooms(&mut rng);
+            let map = build_map(&rooms, &mut rng);
+            let reachable = reachable(&map);
+
+            assert!(rooms.len() > 1, "seed {seed}: only {} room", rooms.len());
+            for room in &rooms {
+                assert!(
+                    reachable.contains(&room.center()),
+                    "seed {seed}: room {room:?} is unreachable"
+                );
+            }
+            for spawn in &map.enemy_spawns {
+                assert!(
+                    reachable.contains(&spawn.as_ivec2()),
+                    "seed {seed}: enemy spawn {spawn} is unreachable"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_dungeon() {
+        let first = generate(&mut StdRng::seed_from_u64(7));
+        let second = generate(&mut StdRng::seed_from_u64(7));
+        assert_eq!(first.spawn, second.spawn);
+        assert_eq!(first.enemy_spawns, second.enemy_spawns);
+        for y in 0..first.height as i32 {
+            for x in 0..first.width as i32 {
+                let cell = IVec2::new(x, y);
+                assert_eq!(first.get(cell), second.get(cell));
+            }
+        }
+    }
+}
@@ -1,28 +1,522 @@
-use bevy::prelude::*;
-use bevy_rapier2d::prelude::Velocity;
+use bevy::{prelude::*, utils::HashMap};
+use bevy_rapier2d::prelude::*;
+use serde::Deserialize;
 
-use crate::player::Player;
+use crate::{
+    combat::{
+        CombatSet, DamageEvent, DamageType, DeathEvent, Health, Knockback, ENEMY_GROUP,
+        ENEMY_PROJECTILE_GROUP, PLAYER_HITBOX_GROUP, PLAYER_SPELL_GROUP, WALL_GROUP,
+    },
+    game_state::AppState,
+    navigation::FlowField,
+    player::{Player, PlayerHitbox},
+    spatial_hash::SpatialHash,
+    spell::particle_bundle,
+    spell_definition::SpellDefinition,
+    sprite_sheets::{tile_index, SpriteSheetsMaps},
+    Score,
+};
 
-const ENEMY_SIZE: f32 = 32.;
+/// Enemies closer than this many times their combined radius push each other
+/// apart.
+const PERSONAL_SPACE: f32 = 1.2;
+/// Enemies start stepping around someone in their way this far before they
+/// would touch.
+const AVOID_DISTANCE: f32 = 40.;
+const SEPARATION_WEIGHT: f32 = 1.5;
+const AVOIDANCE_WEIGHT: f32 = 0.6;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+pub enum EnemyKind {
+    Swarmer,
+    Tank,
+    Caster,
+    Exploder,
+    /// Only spawned as a boss, see `boss::BossKind`.
+    Cyclops,
+    Demon,
+}
+
+pub enum EnemyBehavior {
+    /// Walk straight at the player.
+    Chase,
+    /// Keep `range` away from the player and shoot `spell` at them.
+    Ranged {
+        spell: Handle<SpellDefinition>,
+        range: f32,
+    },
+    /// Detonate when the player gets within `trigger_radius`, hurting them if
+    /// they are inside `radius` when the enemy dies.
+    Explode {
+        trigger_radius: f32,
+        radius: f32,
+        damage: usize,
+    },
+}
+
+pub struct EnemyType {
+    pub sprite_index: usize,
+    pub size: f32,
+    pub health: usize,
+    pub speed: f32,
+    /// Damage per second dealt to the player while touching them.
+    pub contact_damage: f32,
+    pub score_value: u32,
+    /// Value of the experience gem dropped on death.
+    pub experience: u32,
+    pub behavior: EnemyBehavior,
+}
+
+#[derive(Resource)]
+pub struct EnemyRegistry {
+    types: HashMap<EnemyKind, EnemyType>,
+}
+
+impl EnemyRegistry {
+    pub fn get(&self, kind: EnemyKind) -> &EnemyType {
+        &self.types[&kind]
+    }
+}
 
 #[derive(Component)]
-pub struct Enemy;
+pub struct Enemy {
+    pub speed: f32,
+    /// Half the enemy's size, for keeping it apart from the others.
+    pub radius: f32,
+    /// Damage per second dealt to the player while touching them.
+    pub contact_damage: f32,
+    pub score_value: u32,
+    pub experience: u32,
+}
+
+/// Where every enemy is, for neighbour lookups.
+#[derive(Resource, Deref, DerefMut)]
+pub struct EnemySpatialHash(SpatialHash);
+
+impl Default for EnemySpatialHash {
+    fn default() -> Self {
+        EnemySpatialHash(SpatialHash::new(64.))
+    }
+}
+
+#[derive(Component)]
+pub struct RangedAttack {
+    spell: Handle<SpellDefinition>,
+    range: f32,
+    cooldown: Timer,
+}
+
+#[derive(Component)]
+struct Explosive {
+    trigger_radius: f32,
+    radius: f32,
+    damage: usize,
+}
+
+#[derive(Component)]
+pub struct EnemyProjectile {
+    damage: usize,
+    damage_type: DamageType,
+}
+
 pub struct EnemyPlugin;
 
 impl Plugin for EnemyPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system(setup_enemy_movement);
+        app.init_resource::<EnemySpatialHash>()
+            .add_startup_system(setup_enemy_registry)
+            .add_systems(
+                (
+                    update_enemy_spatial_hash,
+                    setup_enemy_movement.after(update_enemy_spatial_hash),
+                    fire_ranged_attacks,
+                    detonate_exploders.before(CombatSet::ResolveDamage),
+                    handle_enemy_projectile_contacts.before(CombatSet::ResolveDamage),
+                    explode_on_death.after(CombatSet::ResolveDamage),
+                    despawn_dead_enemies.after(CombatSet::ResolveDamage),
+                )
+                    .in_set(OnUpdate(AppState::Playing)),
+            );
+    }
+}
+
+pub fn setup_enemy_registry(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let types = HashMap::from_iter([
+        (
+            EnemyKind::Swarmer,
+            EnemyType {
+                sprite_index: tile_index(11, 2),
+                size: 24.,
+                health: 6,
+                speed: 70.,
+                contact_damage: 1.,
+                score_value: 1,
+                experience: 1,
+                behavior: EnemyBehavior::Chase,
+            },
+        ),
+        (
+            EnemyKind::Tank,
+            EnemyType {
+                sprite_index: tile_index(10, 4),
+                size: 48.,
+                health: 60,
+                speed: 25.,
+                contact_damage: 2.,
+                score_value: 5,
+                experience: 6,
+                behavior: EnemyBehavior::Chase,
+            },
+        ),
+        (
+            EnemyKind::Caster,
+            EnemyType {
+                sprite_index: tile_index(10, 1),
+                size: 32.,
+                health: 12,
+                speed: 35.,
+                contact_damage: 1.,
+                score_value: 3,
+                experience: 3,
+                behavior: EnemyBehavior::Ranged {
+                    spell: asset_server.load("spells/enemy_bolt.spell.ron"),
+                    range: 250.,
+                },
+            },
+        ),
+        (
+            EnemyKind::Exploder,
+            EnemyType {
+                sprite_index: tile_index(11, 1),
+                size: 28.,
+                health: 10,
+                speed: 55.,
+                contact_damage: 1.,
+                score_value: 2,
+                experience: 2,
+                behavior: EnemyBehavior::Explode {
+                    trigger_radius: 40.,
+                    radius: 80.,
+                    damage: 3,
+                },
+            },
+        ),
+        (
+            EnemyKind::Cyclops,
+            EnemyType {
+                sprite_index: tile_index(10, 2),
+                size: 96.,
+                health: 400,
+                speed: 30.,
+                contact_damage: 3.,
+                score_value: 50,
+                experience: 40,
+                behavior: EnemyBehavior::Chase,
+            },
+        ),
+        (
+            EnemyKind::Demon,
+            EnemyType {
+                sprite_index: tile_index(10, 3),
+                size: 96.,
+                health: 800,
+                speed: 35.,
+                contact_damage: 3.,
+                score_value: 100,
+                experience: 80,
+                behavior: EnemyBehavior::Chase,
+            },
+        ),
+    ]);
+    commands.insert_resource(EnemyRegistry { types });
+}
+
+pub fn spawn_enemy(
+    commands: &mut Commands,
+    registry: &EnemyRegistry,
+    sprite_sheets_maps: &SpriteSheetsMaps,
+    kind: EnemyKind,
+    translation: Vec3,
+) -> Entity {
+    let enemy_type = registry.get(kind);
+    let mut enemy = commands.spawn((
+        RigidBody::KinematicVelocityBased,
+        Collider::cuboid(enemy_type.size / 2., enemy_type.size / 2.),
+        LockedAxes::ROTATION_LOCKED,
+        Velocity {
+            ..Default::default()
+        },
+        GravityScale(0.),
+        ActiveEvents::COLLISION_EVENTS,
+        CollisionGroups::new(ENEMY_GROUP, PLAYER_HITBOX_GROUP | PLAYER_SPELL_GROUP),
+        (ActiveCollisionTypes::default() | ActiveCollisionTypes::KINEMATIC_KINEMATIC),
+        SpriteSheetBundle {
+            texture_atlas: sprite_sheets_maps.characters_atlas.clone(),
+            sprite: TextureAtlasSprite {
+                index: enemy_type.sprite_index,
+                custom_size: Some(Vec2::splat(enemy_type.size)),
+                ..default()
+            },
+            transform: Transform::from_translation(translation),
+            ..default()
+        },
+        Enemy {
+            speed: enemy_type.speed,
+            radius: enemy_type.size / 2.,
+            contact_damage: enemy_type.contact_damage,
+            score_value: enemy_type.score_value,
+            experience: enemy_type.experience,
+        },
+        Health {
+            total: enemy_type.health,
+            current: enemy_type.health,
+        },
+    ));
+    match &enemy_type.behavior {
+        EnemyBehavior::Chase => {}
+        EnemyBehavior::Ranged { spell, range } => {
+            enemy.insert(RangedAttack {
+                spell: spell.clone(),
+                range: *range,
+                cooldown: Timer::default(),
+            });
+        }
+        EnemyBehavior::Explode {
+            trigger_radius,
+            radius,
+            damage,
+        } => {
+            enemy.insert(Explosive {
+                trigger_radius: *trigger_radius,
+                radius: *radius,
+                damage: *damage,
+            });
+        }
     }
+    enemy.id()
 }
 
-fn setup_enemy_movement(
+pub fn update_enemy_spatial_hash(
+    mut spatial_hash: ResMut<EnemySpatialHash>,
+    query: Query<(Entity, &Transform, &Enemy)>,
+) {
+    spatial_hash.clear();
+    for (entity, transform, enemy) in query.iter() {
+        spatial_hash.insert(entity, transform.translation.truncate(), enemy.radius);
+    }
+}
+
+type EnemyMovementQuery<'a> = (
+    Entity,
+    &'a mut Velocity,
+    &'a Transform,
+    &'a Enemy,
+    Option<&'a RangedAttack>,
+    Option<&'a Knockback>,
+);
+
+pub fn setup_enemy_movement(
     player_query: Query<&Transform, With<Player>>,
-    mut query: Query<(&mut Velocity, &Transform), With<Enemy>>,
-    time: Res<Time>,
+    mut query: Query<EnemyMovementQuery>,
+    flow_field: Res<FlowField>,
+    spatial_hash: Res<EnemySpatialHash>,
 ) {
     let player_transform = player_query.get_single().unwrap();
-    for (mut velocity, transform) in query.iter_mut() {
+    for (entity, mut velocity, transform, enemy, ranged_attack, knockback) in query.iter_mut() {
+        if let Some(knockback) = knockback {
+            velocity.linvel = knockback.velocity();
+            continue;
+        }
+        let direction = player_transform.translation - transform.translation;
+        let in_range = ranged_attack
+            .map(|ranged_attack| direction.length() < ranged_attack.range)
+            .unwrap_or(false);
+        // follow the flow field around walls, and go straight at the player
+        // once there is nothing in between
+        let position = transform.translation.truncate();
+        let target = flow_field
+            .next_waypoint(position)
+            .unwrap_or(player_transform.translation.truncate());
+        let seek = if in_range {
+            Vec2::ZERO
+        } else {
+            (target - position).normalize_or_zero()
+        };
+
+        // Boids-style steering: push away from anyone too close, and step
+        // around anyone in the way, so hordes spread out around the player.
+        let mut separation = Vec2::ZERO;
+        let mut avoidance = Vec2::ZERO;
+        // far enough to see the biggest enemy around
+        let neighbour_distance =
+            (enemy.radius + spatial_hash.max_radius) * PERSONAL_SPACE + AVOID_DISTANCE;
+        for (other, other_position, other_radius) in
+            spatial_hash.nearby(position, neighbour_distance)
+        {
+            if *other == entity {
+                continue;
+            }
+            let offset = position - *other_position;
+            let distance = offset.length();
+            let personal_space = (enemy.radius + other_radius) * PERSONAL_SPACE;
+            if distance < personal_space {
+                // enemies on the exact same spot split along an arbitrary axis
+                let away = if distance > 0. {
+                    offset / distance
+                } else if entity < *other {
+                    Vec2::X
+                } else {
+                    Vec2::NEG_X
+                };
+                separation += away * (1. - distance / personal_space);
+            } else if distance < personal_space + AVOID_DISTANCE && seek.dot(offset) < 0. {
+                // go around on the side we are already on
+                let side = if seek.perp().dot(offset) < 0. {
+                    -1.
+                } else {
+                    1.
+                };
+                let closeness = 1. - (distance - personal_space) / AVOID_DISTANCE;
+                avoidance += seek.perp() * side * closeness;
+            }
+        }
+        let steering = seek + separation * SEPARATION_WEIGHT + avoidance * AVOIDANCE_WEIGHT;
+        velocity.linvel = steering.clamp_length_max(1.) * enemy.speed;
+    }
+}
+
+fn fire_ranged_attacks(
+    mut commands: Commands,
+    player_query: Query<&Transform, With<Player>>,
+    mut query: Query<(&Transform, &mut RangedAttack)>,
+    spell_definitions: Res<Assets<SpellDefinition>>,
+    time: Res<Time>,
+) {
+    let player_transform = player_query.single();
+    for (transform, mut ranged_attack) in query.iter_mut() {
+        ranged_attack.cooldown.tick(time.delta());
         let direction = player_transform.translation - transform.translation;
-        velocity.linvel = Vec2::new(direction.x, direction.y).normalize() * 40.;
+        if direction.length() > ranged_attack.range || !ranged_attack.cooldown.finished() {
+            continue;
+        }
+        let Some(definition) = spell_definitions.get(&ranged_attack.spell) else {
+            continue;
+        };
+        ranged_attack.cooldown = Timer::from_seconds(definition.cooldown, TimerMode::Once);
+        commands.spawn((
+            particle_bundle(
+                definition,
+                transform.translation,
+                direction.truncate().normalize(),
+                definition.speed,
+                1.,
+            ),
+            ActiveEvents::COLLISION_EVENTS,
+            ActiveCollisionTypes::default() | ActiveCollisionTypes::KINEMATIC_KINEMATIC,
+            CollisionGroups::new(ENEMY_PROJECTILE_GROUP, PLAYER_HITBOX_GROUP | WALL_GROUP),
+            EnemyProjectile {
+                damage: definition.damage,
+                damage_type: definition.damage_type,
+            },
+        ));
+    }
+}
+
+fn handle_enemy_projectile_contacts(
+    mut commands: Commands,
+    mut collision_events: EventReader<CollisionEvent>,
+    mut damage_events: EventWriter<DamageEvent>,
+    query: Query<&EnemyProjectile>,
+    hitbox_query: Query<&Parent, With<PlayerHitbox>>,
+) {
+    for collision_event in collision_events.iter() {
+        if let CollisionEvent::Started(e1, e2, _) = collision_event {
+            for (projectile_entity, hitbox_entity) in [(*e1, *e2), (*e2, *e1)] {
+                let (Ok(projectile), Ok(player)) = (
+                    query.get(projectile_entity),
+                    hitbox_query.get(hitbox_entity),
+                ) else {
+                    continue;
+                };
+                commands.entity(projectile_entity).despawn();
+                damage_events.send(DamageEvent {
+                    source: projectile_entity,
+                    target: player.get(),
+                    amount: projectile.damage,
+                    damage_type: projectile.damage_type,
+                    crit: false,
+                });
+            }
+        }
+    }
+}
+
+fn detonate_exploders(
+    mut damage_events: EventWriter<DamageEvent>,
+    player_query: Query<&Transform, With<Player>>,
+    query: Query<(Entity, &Transform, &Health, &Explosive)>,
+) {
+    let player_transform = player_query.single();
+    for (entity, transform, health, explosive) in query.iter() {
+        let distance = player_transform
+            .translation
+            .truncate()
+            .distance(transform.translation.truncate());
+        if distance < explosive.trigger_radius {
+            damage_events.send(DamageEvent {
+                source: entity,
+                target: entity,
+                amount: health.current,
+                damage_type: DamageType::Explosion,
+                crit: false,
+            });
+        }
+    }
+}
+
+fn explode_on_death(
+    mut death_events: EventReader<DeathEvent>,
+    mut damage_events: EventWriter<DamageEvent>,
+    player_query: Query<(Entity, &Transform), With<Player>>,
+    query: Query<(&Transform, &Explosive)>,
+) {
+    let (player_entity, player_transform) = player_query.single();
+    for death_event in death_events.iter() {
+        let Ok((transform, explosive)) = query.get(death_event.entity) else {
+            continue;
+        };
+        let distance = player_transform
+            .translation
+            .truncate()
+            .distance(transform.translation.truncate());
+        if distance < explosive.radius {
+            damage_events.send(DamageEvent {
+                source: death_event.entity,
+                target: player_entity,
+                amount: explosive.damage,
+                damage_type: DamageType::Explosion,
+                crit: false,
+            });
+        }
+    }
+}
+
+/// Only the player's kills count towards the score. Exploders that detonate
+/// themselves next to the player are worth nothing.
+pub fn despawn_dead_enemies(
+    mut commands: Commands,
+    mut death_events: EventReader<DeathEvent>,
+    query: Query<&Enemy>,
+    mut score: ResMut<Score>,
+) {
+    for death_event in death_events.iter() {
+        if let Ok(enemy) = query.get(death_event.entity) {
+            commands.entity(death_event.entity).despawn_recursive();
+            if death_event.source == death_event.entity {
+                continue;
+            }
+            score.value += enemy.score_value;
+            score.kills += 1;
+        }
     }
 }
@@ -0,0 +1,134 @@
+use bevy::prelude::*;
+
+use crate::{
+    combat::{CombatSet, DeathEvent},
+    enemy::Enemy,
+    game_state::AppState,
+    player::Player,
+    sprite_sheets::{tile_index, SpriteSheetsMaps},
+};
+
+const GEM_SIZE: f32 = 16.;
+/// Gems closer than this start flying towards the player.
+const PICKUP_RADIUS: f32 = 100.;
+const COLLECT_RADIUS: f32 = 12.;
+const GEM_SPEED: f32 = 250.;
+
+#[derive(Component)]
+pub struct ExperienceGem {
+    value: u32,
+}
+
+#[derive(Component)]
+pub struct Experience {
+    pub level: u32,
+    pub current: u32,
+    /// Level ups gained but not yet spent on an upgrade.
+    pub pending_level_ups: u32,
+}
+
+impl Default for Experience {
+    fn default() -> Self {
+        Experience {
+            level: 1,
+            current: 0,
+            pending_level_ups: 0,
+        }
+    }
+}
+
+impl Experience {
+    /// Experience needed to go from the current level to the next one.
+    pub fn required(&self) -> u32 {
+        (5. * (self.level as f32).powf(1.5)).round() as u32
+    }
+
+    pub fn add(&mut self, value: u32) {
+        self.current += value;
+        while self.current >= self.required() {
+            self.current -= self.required();
+            self.level += 1;
+            self.pending_level_ups += 1;
+        }
+    }
+}
+
+pub struct ExperiencePlugin;
+
+impl Plugin for ExperiencePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            (
+                drop_experience_gems.after(CombatSet::ResolveDamage),
+                collect_experience_gems,
+                check_level_up,
+            )
+                .in_set(OnUpdate(AppState::Playing)),
+        );
+    }
+}
+
+fn drop_experience_gems(
+    mut commands: Commands,
+    mut death_events: EventReader<DeathEvent>,
+    query: Query<(&Transform, &Enemy)>,
+    sprite_sheets_maps: Res<SpriteSheetsMaps>,
+) {
+    for death_event in death_events.iter() {
+        let Ok((transform, enemy)) = query.get(death_event.entity) else {
+            continue;
+        };
+        commands.spawn((
+            SpriteSheetBundle {
+                texture_atlas: sprite_sheets_maps.characters_atlas.clone(),
+                sprite: TextureAtlasSprite {
+                    index: tile_index(10, 9),
+                    custom_size: Some(Vec2::splat(GEM_SIZE)),
+                    ..default()
+                },
+                transform: Transform::from_xyz(
+                    transform.translation.x,
+                    transform.translation.y,
+                    -0.1,
+                ),
+                ..default()
+            },
+            Name::from("Experience Gem"),
+            ExperienceGem {
+                value: enemy.experience,
+            },
+        ));
+    }
+}
+
+fn collect_experience_gems(
+    mut commands: Commands,
+    mut player_query: Query<(&Transform, &mut Experience), With<Player>>,
+    mut query: Query<(Entity, &mut Transform, &ExperienceGem), Without<Player>>,
+    time: Res<Time>,
+) {
+    let (player_transform, mut experience) = player_query.single_mut();
+    let player_translation = player_transform.translation.truncate();
+    for (entity, mut transform, gem) in query.iter_mut() {
+        let offset = player_translation - transform.translation.truncate();
+        let distance = offset.length();
+        if distance < COLLECT_RADIUS {
+            experience.add(gem.value);
+            commands.entity(entity).despawn();
+        } else if distance < PICKUP_RADIUS {
+            let step = (GEM_SPEED * time.delta_seconds()).min(distance);
+            transform.translation += (offset / distance * step).extend(0.);
+        }
+    }
+}
+
+fn check_level_up(
+    query: Query<&Experience, (With<Player>, Changed<Experience>)>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if let Ok(experience) = query.get_single() {
+        if experience.pending_level_ups > 0 {
+            next_state.set(AppState::LevelUp);
+        }
+    }
+}
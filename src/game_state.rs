@@ -0,0 +1,268 @@
+use bevy::{prelude::*, window::close_on_esc};
+use bevy_rapier2d::prelude::RapierConfiguration;
+
+use crate::{controls::gamepad_just_pressed, player::Cursor, Score};
+
+#[derive(States, Debug, Clone, Copy, Default, Eq, PartialEq, Hash)]
+pub enum AppState {
+    #[default]
+    MainMenu,
+    Playing,
+    Paused,
+    LevelUp,
+    GameOver,
+}
+
+#[derive(Component)]
+struct MainMenuScreen;
+
+#[derive(Component)]
+struct PauseScreen;
+
+#[derive(Component)]
+struct GameOverScreen;
+
+/// Root entities that belong to a run: everything but the window, the camera
+/// and the cursor.
+type RunEntityFilter = (
+    Without<Parent>,
+    Without<Window>,
+    Without<Camera>,
+    Without<Cursor>,
+);
+
+pub struct GameStatePlugin;
+
+impl Plugin for GameStatePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_state::<AppState>()
+            .add_system(spawn_main_menu.in_schedule(OnEnter(AppState::MainMenu)))
+            .add_system(despawn_screen::<MainMenuScreen>.in_schedule(OnExit(AppState::MainMenu)))
+            .add_systems((start_run, close_on_esc).in_set(OnUpdate(AppState::MainMenu)))
+            .add_system(pause_game.in_set(OnUpdate(AppState::Playing)))
+            .add_system(spawn_pause_screen.in_schedule(OnEnter(AppState::Paused)))
+            .add_system(despawn_screen::<PauseScreen>.in_schedule(OnExit(AppState::Paused)))
+            .add_system(resume_game.in_set(OnUpdate(AppState::Paused)))
+            .add_system(spawn_game_over_screen.in_schedule(OnEnter(AppState::GameOver)))
+            .add_system(cleanup_run.in_schedule(OnExit(AppState::GameOver)))
+            .add_system(handle_game_over_input.in_set(OnUpdate(AppState::GameOver)))
+            .add_system(toggle_physics.run_if(state_changed::<AppState>()));
+    }
+}
+
+fn spawn_screen<T: Component>(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    marker: T,
+    lines: &[(&str, f32)],
+) {
+    let font = asset_server.load("fonts/DMSans-Regular.ttf");
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                    flex_direction: FlexDirection::Column,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: Color::rgba(0., 0., 0., 0.6).into(),
+                ..default()
+            },
+            marker,
+        ))
+        .with_children(|parent| {
+            for (text, font_size) in lines {
+                parent.spawn(TextBundle::from_section(
+                    *text,
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: *font_size,
+                        color: Color::WHITE,
+                    },
+                ));
+            }
+        });
+}
+
+/// A row of buttons along the bottom of the screen, for settings shown over
+/// a menu screen.
+pub fn settings_panel() -> NodeBundle {
+    NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                bottom: Val::Px(40.),
+                left: Val::Px(0.),
+                right: Val::Px(0.),
+                ..default()
+            },
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            gap: Size::all(Val::Px(10.)),
+            ..default()
+        },
+        ..default()
+    }
+}
+
+/// A menu button with a line of text. `tag` goes on both the button and its
+/// text, so one can be found for clicks and the other for relabeling.
+pub fn spawn_button<T: Component + Copy>(
+    parent: &mut ChildBuilder,
+    font: &Handle<Font>,
+    label: String,
+    tag: T,
+) {
+    parent
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    padding: UiRect::new(Val::Px(16.), Val::Px(16.), Val::Px(8.), Val::Px(8.)),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: Color::rgb(0.2, 0.2, 0.3).into(),
+                ..default()
+            },
+            tag,
+        ))
+        .with_children(|button| {
+            button.spawn((
+                TextBundle::from_section(
+                    label,
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 22.,
+                        color: Color::WHITE,
+                    },
+                ),
+                tag,
+            ));
+        });
+}
+
+pub fn despawn_screen<T: Component>(mut commands: Commands, query: Query<Entity, With<T>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn spawn_main_menu(mut commands: Commands, asset_server: Res<AssetServer>) {
+    spawn_screen(
+        &mut commands,
+        &asset_server,
+        MainMenuScreen,
+        &[
+            ("Dungeon Survivors", 60.),
+            ("Press Enter or Start to start", 30.),
+            ("Esc to quit", 20.),
+        ],
+    );
+}
+
+fn spawn_pause_screen(mut commands: Commands, asset_server: Res<AssetServer>) {
+    spawn_screen(
+        &mut commands,
+        &asset_server,
+        PauseScreen,
+        &[("Paused", 60.), ("Press Esc or Start to resume", 30.)],
+    );
+}
+
+fn spawn_game_over_screen(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    score: Res<Score>,
+) {
+    let score_line = format!("Final score: {}", score.value);
+    spawn_screen(
+        &mut commands,
+        &asset_server,
+        GameOverScreen,
+        &[
+            ("Game Over", 60.),
+            (&score_line, 40.),
+            ("Press Enter or Start to restart", 30.),
+            ("Esc or B for main menu", 20.),
+        ],
+    );
+}
+
+fn start_run(
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepad_input: Res<Input<GamepadButton>>,
+    gamepads: Res<Gamepads>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Return)
+        || gamepad_just_pressed(&gamepads, &gamepad_input, GamepadButtonType::Start)
+    {
+        next_state.set(AppState::Playing);
+    }
+}
+
+fn pause_game(
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepad_input: Res<Input<GamepadButton>>,
+    gamepads: Res<Gamepads>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Escape)
+        || gamepad_just_pressed(&gamepads, &gamepad_input, GamepadButtonType::Start)
+    {
+        next_state.set(AppState::Paused);
+    }
+}
+
+fn resume_game(
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepad_input: Res<Input<GamepadButton>>,
+    gamepads: Res<Gamepads>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Escape)
+        || gamepad_just_pressed(&gamepads, &gamepad_input, GamepadButtonType::Start)
+    {
+        next_state.set(AppState::Playing);
+    }
+}
+
+fn handle_game_over_input(
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepad_input: Res<Input<GamepadButton>>,
+    gamepads: Res<Gamepads>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let gamepad_pressed = |button| gamepad_just_pressed(&gamepads, &gamepad_input, button);
+    if keyboard_input.just_pressed(KeyCode::Return)
+        || gamepad_pressed(GamepadButtonType::Start)
+        || gamepad_pressed(GamepadButtonType::South)
+    {
+        next_state.set(AppState::Playing);
+    } else if keyboard_input.just_pressed(KeyCode::Escape)
+        || gamepad_pressed(GamepadButtonType::East)
+    {
+        next_state.set(AppState::MainMenu);
+    }
+}
+
+/// Tears down everything left from the previous run, including the game over
+/// screen. Run setup systems spawn a fresh player and wave the next time the
+/// game enters `AppState::Playing`.
+fn cleanup_run(
+    mut commands: Commands,
+    query: Query<Entity, RunEntityFilter>,
+    mut score: ResMut<Score>,
+) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    *score = Score::default();
+}
+
+fn toggle_physics(state: Res<State<AppState>>, mut rapier_config: ResMut<RapierConfiguration>) {
+    rapier_config.physics_pipeline_active = state.0 == AppState::Playing;
+}
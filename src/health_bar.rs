@@ -0,0 +1,139 @@
+use bevy::{prelude::*, sprite::Anchor, utils::HashSet};
+
+use crate::{
+    boss::Boss,
+    combat::{CombatSet, DamageEvent, Health},
+    enemy::Enemy,
+    game_state::AppState,
+};
+
+/// Seconds a bar stays up after the enemy was last hit.
+const HEALTH_BAR_TIMEOUT: f32 = 2.5;
+const HEALTH_BAR_HEIGHT: f32 = 3.;
+const MIN_HEALTH_BAR_WIDTH: f32 = 20.;
+/// Gap between the top of the enemy and its bar.
+const HEALTH_BAR_OFFSET: f32 = 6.;
+
+/// Health bar floating over an enemy that has been hit. Bosses are left out,
+/// they have their own bar at the top of the screen.
+#[derive(Component)]
+struct EnemyHealthBar {
+    /// Child holding the background and the fill.
+    root: Entity,
+    fill: Entity,
+    width: f32,
+    timer: Timer,
+}
+
+pub struct HealthBarPlugin;
+
+impl Plugin for HealthBarPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            (
+                show_health_bars.after(CombatSet::ResolveDamage),
+                update_health_bars.after(show_health_bars),
+            )
+                .in_set(OnUpdate(AppState::Playing)),
+        );
+    }
+}
+
+/// Spawns a bar on an enemy's first hit and brings a hidden one back on the
+/// next ones.
+fn show_health_bars(
+    mut commands: Commands,
+    mut damage_events: EventReader<DamageEvent>,
+    query: Query<(&Enemy, &Health, Option<&EnemyHealthBar>), Without<Boss>>,
+    mut bar_query: Query<&mut Visibility>,
+    mut spawned: Local<HashSet<Entity>>,
+) {
+    // several hits in one frame only need one bar
+    spawned.clear();
+    for damage_event in damage_events.iter() {
+        let Ok((enemy, health, health_bar)) = query.get(damage_event.target) else {
+            continue;
+        };
+        if health.current == 0 {
+            continue;
+        }
+        if let Some(health_bar) = health_bar {
+            if let Ok(mut visibility) = bar_query.get_mut(health_bar.root) {
+                *visibility = Visibility::Inherited;
+            }
+            continue;
+        }
+        if !spawned.insert(damage_event.target) {
+            continue;
+        }
+
+        let width = (enemy.radius * 2.).max(MIN_HEALTH_BAR_WIDTH);
+        let fraction = health.current as f32 / health.total as f32;
+        let root = commands
+            .spawn(SpatialBundle::from_transform(Transform::from_xyz(
+                0.,
+                enemy.radius + HEALTH_BAR_OFFSET,
+                0.1,
+            )))
+            .id();
+        let fill = commands
+            .spawn(SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgb(0.8, 0.1, 0.1),
+                    custom_size: Some(Vec2::new(width * fraction, HEALTH_BAR_HEIGHT)),
+                    anchor: Anchor::CenterLeft,
+                    ..default()
+                },
+                transform: Transform::from_xyz(-width / 2., 0., 0.01),
+                ..default()
+            })
+            .id();
+        let background = commands
+            .spawn(SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgba(0., 0., 0., 0.7),
+                    custom_size: Some(Vec2::new(width, HEALTH_BAR_HEIGHT)),
+                    ..default()
+                },
+                ..default()
+            })
+            .id();
+        commands.entity(root).push_children(&[background, fill]);
+        commands
+            .entity(damage_event.target)
+            .add_child(root)
+            .insert(EnemyHealthBar {
+                root,
+                fill,
+                width,
+                timer: Timer::from_seconds(HEALTH_BAR_TIMEOUT, TimerMode::Once),
+            });
+    }
+}
+
+/// Keeps the fill in line with the enemy's health, and hides the bar once the
+/// enemy hasn't been hit for a while.
+fn update_health_bars(
+    mut query: Query<(Ref<Health>, &mut EnemyHealthBar)>,
+    mut sprite_query: Query<&mut Sprite>,
+    mut visibility_query: Query<&mut Visibility>,
+    time: Res<Time>,
+) {
+    for (health, mut health_bar) in query.iter_mut() {
+        if health.is_changed() {
+            health_bar.timer.reset();
+            if let Ok(mut sprite) = sprite_query.get_mut(health_bar.fill) {
+                let fraction = health.current as f32 / health.total as f32;
+                sprite.custom_size = Some(Vec2::new(
+                    health_bar.width * fraction.clamp(0., 1.),
+                    HEALTH_BAR_HEIGHT,
+                ));
+            }
+        }
+        if health_bar.timer.tick(time.delta()).just_finished() {
+            if let Ok(mut visibility) = visibility_query.get_mut(health_bar.root) {
+                *visibility = Visibility::Hidden;
+            }
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use bevy::{prelude::*, time::Stopwatch};
+
+use crate::{
+    experience::Experience, game_state::AppState, player::Player, spell::Mana, wave::Wave, Score,
+};
+
+const MANA_BAR_WIDTH: f32 = 200.;
+const BAR_HEIGHT: f32 = 10.;
+
+/// Root of the in-run interface.
+#[derive(Component)]
+struct Hud;
+
+/// What a HUD text node shows. `update_hud_text` rewrites each one by its tag.
+#[derive(Component, Clone, Copy)]
+enum HudText {
+    Time,
+    Wave,
+    Kills,
+    Score,
+}
+
+/// The inner node of a bar, sized to how full it is.
+#[derive(Component, Clone, Copy)]
+enum HudBar {
+    Mana,
+    Experience,
+}
+
+/// Time spent playing this run. Pauses and level up screens don't count.
+#[derive(Resource, Default)]
+struct RunClock(Stopwatch);
+
+pub struct HudPlugin;
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RunClock>()
+            .add_system(
+                spawn_hud
+                    .run_if(not(any_with_component::<Hud>()))
+                    .in_schedule(OnEnter(AppState::Playing)),
+            )
+            .add_systems(
+                (
+                    tick_run_clock,
+                    update_hud_text.after(tick_run_clock),
+                    update_hud_bars,
+                )
+                    .in_set(OnUpdate(AppState::Playing)),
+            );
+    }
+}
+
+/// Spawned at the start of every run, which also restarts the clock.
+fn spawn_hud(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(RunClock::default());
+    let style = TextStyle {
+        font: asset_server.load("fonts/DMSans-Regular.ttf"),
+        font_size: 24.,
+        color: Color::WHITE,
+    };
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                    position_type: PositionType::Absolute,
+                    flex_direction: FlexDirection::Column,
+                    justify_content: JustifyContent::SpaceBetween,
+                    ..default()
+                },
+                ..default()
+            },
+            Hud,
+            Name::from("Hud"),
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        margin: UiRect::all(Val::Px(5.)),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|texts| {
+                    for tag in [HudText::Time, HudText::Wave, HudText::Kills, HudText::Score] {
+                        texts.spawn((TextBundle::from_section("", style.clone()), tag));
+                    }
+                });
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        gap: Size::all(Val::Px(4.)),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|bars| {
+                    for (color, tag, size) in [
+                        (
+                            Color::rgb(0.2, 0.4, 0.9),
+                            HudBar::Mana,
+                            Size::new(Val::Px(MANA_BAR_WIDTH), Val::Px(BAR_HEIGHT)),
+                        ),
+                        (
+                            Color::rgb(0.3, 0.8, 0.3),
+                            HudBar::Experience,
+                            Size::new(Val::Percent(100.), Val::Px(BAR_HEIGHT)),
+                        ),
+                    ] {
+                        bars.spawn(NodeBundle {
+                            style: Style { size, ..default() },
+                            background_color: Color::rgba(0., 0., 0., 0.6).into(),
+                            ..default()
+                        })
+                        .with_children(|background| {
+                            background.spawn((
+                                NodeBundle {
+                                    style: Style {
+                                        size: Size::new(Val::Percent(0.), Val::Percent(100.)),
+                                        ..default()
+                                    },
+                                    background_color: color.into(),
+                                    ..default()
+                                },
+                                tag,
+                            ));
+                        });
+                    }
+                });
+        });
+}
+
+fn tick_run_clock(mut clock: ResMut<RunClock>, time: Res<Time>) {
+    clock.0.tick(time.delta());
+}
+
+fn update_hud_text(
+    mut query: Query<(&mut Text, &HudText)>,
+    clock: Res<RunClock>,
+    wave_query: Query<&Wave>,
+    score: Res<Score>,
+) {
+    for (mut text, tag) in query.iter_mut() {
+        text.sections[0].value = match tag {
+            HudText::Time => {
+                let seconds = clock.0.elapsed().as_secs();
+                format!("{:02}:{:02}", seconds / 60, seconds % 60)
+            }
+            HudText::Wave => match wave_query.get_single() {
+                Ok(wave) => format!(
+                    "Wave {} - next in {:.0}s",
+                    wave.index,
+                    wave.time_to_next().ceil()
+                ),
+                Err(_) => String::new(),
+            },
+            HudText::Kills => format!("Kills: {}", score.kills),
+            HudText::Score => format!("Score: {}", score.value),
+        };
+    }
+}
+
+fn update_hud_bars(
+    mut query: Query<(&mut Style, &HudBar)>,
+    player_query: Query<(&Mana, &Experience), With<Player>>,
+) {
+    let Ok((mana, experience)) = player_query.get_single() else {
+        return;
+    };
+    for (mut style, tag) in query.iter_mut() {
+        let fraction = match tag {
+            HudBar::Mana => mana.current / mana.total,
+            HudBar::Experience => experience.current as f32 / experience.required() as f32,
+        };
+        style.size.width = Val::Percent(fraction.clamp(0., 1.) * 100.);
+    }
+}
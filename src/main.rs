@@ -1,94 +1,141 @@
+#[cfg(test)]
+mod benchmark;
+mod boss;
 mod combat;
+mod controls;
+mod damage_numbers;
+mod dungeon;
+mod dungeon_generator;
 mod enemy;
+mod experience;
+mod game_state;
+mod health_bar;
+mod hud;
+mod music;
+mod navigation;
 mod player;
+mod rng;
+mod sound;
+mod spatial_hash;
 mod spell;
+mod spell_definition;
 mod sprite_sheets;
+mod stats;
+mod synth;
+mod upgrade;
 mod utils;
 mod wave;
+mod wave_schedule;
+mod weapon;
 
-use bevy::{prelude::*, text::TextStyle, window::PrimaryWindow};
+use bevy::{prelude::*, window::PrimaryWindow};
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
 use bevy_rapier2d::prelude::*;
+use boss::BossPlugin;
 use combat::CombatPlugin;
+use controls::ControlsPlugin;
+use damage_numbers::DamageNumberPlugin;
+use dungeon::DungeonPlugin;
 use enemy::EnemyPlugin;
-use player::PlayerPlugin;
+use experience::ExperiencePlugin;
+use game_state::GameStatePlugin;
+use health_bar::HealthBarPlugin;
+use hud::HudPlugin;
+use music::MusicPlugin;
+use navigation::NavigationPlugin;
+use player::{Cursor, Player, PlayerPlugin};
+use rng::RngPlugin;
+use sound::SoundPlugin;
 use spell::SpellPlugin;
 use sprite_sheets::SpriteSheetPlugin;
+use stats::StatsPlugin;
+use upgrade::UpgradePlugin;
 use wave::WavePlugin;
+use weapon::WeaponPlugin;
 
 fn main() {
     let mut app = App::new();
-    app.add_plugins(DefaultPlugins.set(WindowPlugin {
-        primary_window: Some(Window {
-            title: "Bevy game".to_string(),
-            resolution: (1280., 720.).into(),
-            canvas: Some("#bevy".to_owned()),
-            //fit_canvas_to_parent: true,
-            ..default()
-        }),
-        ..default()
-    }))
+    app.add_plugins(
+        DefaultPlugins
+            .set(WindowPlugin {
+                primary_window: Some(Window {
+                    title: "Bevy game".to_string(),
+                    resolution: (1280., 720.).into(),
+                    canvas: Some("#bevy".to_owned()),
+                    //fit_canvas_to_parent: true,
+                    ..default()
+                }),
+                ..default()
+            })
+            .set(AssetPlugin {
+                // hot-reload spell definitions and other assets while developing
+                watch_for_changes: cfg!(feature = "debug"),
+                ..default()
+            }),
+    )
+    .add_plugin(GameStatePlugin)
+    .add_plugin(RngPlugin)
+    .add_plugin(ControlsPlugin)
+    .add_plugin(DungeonPlugin)
+    .add_plugin(NavigationPlugin)
     .add_plugin(PlayerPlugin)
     .add_plugin(EnemyPlugin)
     .add_plugin(WavePlugin)
+    .add_plugin(BossPlugin)
     .add_plugin(SpellPlugin)
+    .add_plugin(WeaponPlugin)
     .add_plugin(SpriteSheetPlugin)
     .add_plugin(CombatPlugin)
+    .add_plugin(ExperiencePlugin)
+    .add_plugin(UpgradePlugin)
+    .add_plugin(StatsPlugin)
+    .add_plugin(HudPlugin)
+    .add_plugin(DamageNumberPlugin)
+    .add_plugin(HealthBarPlugin)
+    .add_plugin(SoundPlugin)
+    .add_plugin(MusicPlugin)
     .add_plugin(RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(100.0))
-    .add_system(bevy::window::close_on_esc)
-    .insert_resource(Score { value: 0 });
+    .init_resource::<Score>();
 
     if cfg!(feature = "debug") {
         app.add_plugin(WorldInspectorPlugin::new())
-            .add_plugin(RapierDebugRenderPlugin::default());
+            .add_plugin(RapierDebugRenderPlugin::default())
+            .add_system(display_events);
     }
     app.add_startup_system(setup_camera)
         .add_system(camera_follow_player)
-        .add_system(display_events)
-        .add_system(update_score)
         .run();
 }
 
-fn setup_camera(
-    mut commands: Commands,
-    query: Query<&Window, With<PrimaryWindow>>,
-    asset_server: Res<AssetServer>,
-) {
+fn setup_camera(mut commands: Commands, query: Query<&Window, With<PrimaryWindow>>) {
     let window = query.get_single().unwrap();
     commands.spawn(Camera2dBundle {
         transform: Transform::from_xyz(window.width() / 2., window.height() / 2., 0.),
         ..default()
     });
-    commands.spawn(
-        TextBundle::from_section(
-            "Score: 0",
-            TextStyle {
-                font_size: 30.,
-                color: Color::BLACK,
-                font: asset_server.load("fonts/DMSans-Regular.ttf"),
-            },
-        )
-        .with_style(Style {
-            position_type: PositionType::Absolute,
-            position: UiRect {
-                top: Val::Px(5.),
-                left: Val::Px(5.),
-                ..default()
-            },
-            ..default()
-        }),
-    );
 }
 
+type CameraFilter = (With<Camera2d>, Without<Player>, Without<Cursor>);
+
 fn camera_follow_player(
-    mut query: Query<&mut Transform, (With<Camera2d>, Without<KinematicCharacterController>)>,
-    player_query: Query<&Transform, With<KinematicCharacterController>>,
+    mut query: Query<&mut Transform, CameraFilter>,
+    player_query: Query<&Transform, With<Player>>,
+    mut cursor_query: Query<(&mut Cursor, &mut Transform), Without<Player>>,
 ) {
     if let Ok(player_transform) = player_query.get_single() {
         let player_translation = player_transform.translation;
         if let Ok(mut camera_transform) = query.get_single_mut() {
-            camera_transform.translation.x = player_translation.x;
-            camera_transform.translation.y = player_translation.y;
+            let offset = Vec3::new(
+                player_translation.x - camera_transform.translation.x,
+                player_translation.y - camera_transform.translation.y,
+                0.,
+            );
+            camera_transform.translation += offset;
+            // the cursor lives in world space, keep it at the same spot on screen
+            if let Ok((mut cursor, mut cursor_transform)) = cursor_query.get_single_mut() {
+                cursor.translation += offset;
+                cursor_transform.translation += offset;
+            }
         }
     }
 }
@@ -106,11 +153,8 @@ fn display_events(
     }
 }
 
-#[derive(Resource)]
+#[derive(Resource, Default)]
 pub struct Score {
     value: u32,
-}
-
-fn update_score(score: Res<Score>, mut query: Query<&mut Text>) {
-    query.get_single_mut().unwrap().sections[0].value = format!("Score: {}", score.value);
+    kills: u32,
 }
@@ -0,0 +1,132 @@
+use bevy::{audio::AddAudioSource, prelude::*, utils::HashMap};
+
+use crate::{
+    boss::Boss,
+    enemy::Enemy,
+    game_state::AppState,
+    sound::Volume,
+    synth::{Song, SongLoader},
+    wave::Wave,
+};
+
+/// How fast a track's volume moves towards its target, in full volume per
+/// second. Two seconds for a complete crossfade.
+const CROSSFADE_SPEED: f32 = 0.5;
+/// Enemies alive for the intense layer to reach full volume.
+const FULL_INTENSITY_ENEMIES: f32 = 80.;
+/// From this wave on the music is fully intense however many enemies are left.
+const FULL_INTENSITY_WAVE: f32 = 10.;
+
+/// Looping songs under `assets/audio/music/`, rendered by `synth` as they
+/// load. The calm and intense layers share a tempo and length so they play on
+/// top of each other, and all of them keep looping muted when not heard, so
+/// fading between them never restarts a song.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Track {
+    Calm,
+    Intense,
+    Boss,
+}
+
+impl Track {
+    const ALL: [Track; 3] = [Track::Calm, Track::Intense, Track::Boss];
+
+    fn path(&self) -> &'static str {
+        match self {
+            Track::Calm => "audio/music/calm.song.ron",
+            Track::Intense => "audio/music/intense.song.ron",
+            Track::Boss => "audio/music/boss.song.ron",
+        }
+    }
+}
+
+#[derive(Resource)]
+struct Music {
+    sinks: HashMap<Track, Handle<AudioSink>>,
+    /// Where each track is in its fade, from 0 to 1, before the music volume
+    /// setting is applied.
+    levels: HashMap<Track, f32>,
+}
+
+pub struct MusicPlugin;
+
+impl Plugin for MusicPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_audio_source::<Song>()
+            .init_asset_loader::<SongLoader>()
+            .add_startup_system(start_music)
+            .add_system(mix_music);
+    }
+}
+
+/// Starts every track muted. `mix_music` fades them in and out from there.
+fn start_music(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    audio: Res<Audio<Song>>,
+    audio_sinks: Res<Assets<AudioSink>>,
+) {
+    let sinks = Track::ALL
+        .into_iter()
+        .map(|track| {
+            let sink = audio.play_with_settings(
+                asset_server.load(track.path()),
+                PlaybackSettings::LOOP.with_volume(0.),
+            );
+            // the returned handle is weak and can't control playback
+            (track, audio_sinks.get_handle(sink))
+        })
+        .collect();
+    commands.insert_resource(Music {
+        sinks,
+        levels: HashMap::default(),
+    });
+}
+
+/// The boss track while a boss is alive, otherwise the calm layer with the
+/// intense one fading in on top as the enemy count and wave go up. Menus get
+/// the calm layer alone.
+#[allow(clippy::too_many_arguments)]
+fn mix_music(
+    mut music: ResMut<Music>,
+    audio_sinks: Res<Assets<AudioSink>>,
+    volume: Res<Volume>,
+    state: Res<State<AppState>>,
+    wave_query: Query<&Wave>,
+    enemy_query: Query<(), With<Enemy>>,
+    boss_query: Query<(), With<Boss>>,
+    time: Res<Time>,
+) {
+    let in_run = matches!(
+        state.0,
+        AppState::Playing | AppState::Paused | AppState::LevelUp
+    );
+    let targets = if in_run && !boss_query.is_empty() {
+        [(Track::Calm, 0.), (Track::Intense, 0.), (Track::Boss, 1.)]
+    } else {
+        let intensity = if in_run {
+            let enemies = enemy_query.iter().count() as f32 / FULL_INTENSITY_ENEMIES;
+            let wave = wave_query
+                .get_single()
+                .map_or(0., |wave| wave.index as f32 / FULL_INTENSITY_WAVE);
+            enemies.max(wave).min(1.)
+        } else {
+            0.
+        };
+        [
+            (Track::Calm, 1.),
+            (Track::Intense, intensity),
+            (Track::Boss, 0.),
+        ]
+    };
+
+    let step = CROSSFADE_SPEED * time.delta_seconds();
+    let Music { sinks, levels } = &mut *music;
+    for (track, target) in targets {
+        let level = levels.entry(track).or_default();
+        *level += (target - *level).clamp(-step, step);
+        if let Some(sink) = audio_sinks.get(&sinks[&track]) {
+            sink.set_volume(*level * volume.music());
+        }
+    }
+}
@@ -0,0 +1,266 @@
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use bevy::{prelude::*, transform::TransformSystem, utils::HashSet};
+use bevy_rapier2d::{na, prelude::*};
+
+use crate::{dungeon::TILE_SIZE, game_state::AppState, player::Player};
+
+/// Navigation cells line up with the dungeon tiles.
+const CELL_SIZE: f32 = TILE_SIZE;
+/// Free cells kept around the obstacles, so enemies outside the walls can
+/// still find their way.
+const BOUNDS_MARGIN: i32 = 4;
+const STRAIGHT_COST: u32 = 10;
+const DIAGONAL_COST: u32 = 14;
+
+const NEIGHBOURS: [IVec2; 8] = [
+    IVec2::new(1, 0),
+    IVec2::new(-1, 0),
+    IVec2::new(0, 1),
+    IVec2::new(0, -1),
+    IVec2::new(1, 1),
+    IVec2::new(1, -1),
+    IVec2::new(-1, 1),
+    IVec2::new(-1, -1),
+];
+
+/// Distance to the player from every free cell around the static colliders.
+/// Enemies walk towards whichever neighbouring cell is closer to the player,
+/// which takes them around walls without any per-enemy path search.
+#[derive(Resource, Default)]
+pub struct FlowField {
+    min: IVec2,
+    size: IVec2,
+    blocked: Vec<bool>,
+    /// Path cost to the target, `u32::MAX` where it can't be reached.
+    cost: Vec<u32>,
+    /// The player's cell the costs were computed for.
+    target: Option<IVec2>,
+    /// Static colliders the grid was built from, to notice when one goes away.
+    obstacles: HashSet<Entity>,
+}
+
+impl FlowField {
+    pub fn cell_at(point: Vec2) -> IVec2 {
+        (point / CELL_SIZE).round().as_ivec2()
+    }
+
+    fn index(&self, cell: IVec2) -> Option<usize> {
+        let local = cell - self.min;
+        if local.x < 0 || local.y < 0 || local.x >= self.size.x || local.y >= self.size.y {
+            return None;
+        }
+        Some((local.y * self.size.x + local.x) as usize)
+    }
+
+    fn is_free(&self, cell: IVec2) -> bool {
+        self.index(cell).is_some_and(|index| !self.blocked[index])
+    }
+
+    fn cost(&self, cell: IVec2) -> u32 {
+        self.index(cell).map_or(u32::MAX, |index| self.cost[index])
+    }
+
+    /// Marks every cell the static colliders overlap as blocked.
+    fn rebuild(&mut self, obstacles: Vec<(Entity, Rect)>) {
+        self.obstacles = obstacles.iter().map(|(entity, _)| *entity).collect();
+        self.target = None;
+        if obstacles.is_empty() {
+            self.size = IVec2::ZERO;
+            self.blocked.clear();
+            self.cost.clear();
+            return;
+        }
+
+        let bounds = obstacles
+            .iter()
+            .fold(obstacles[0].1, |bounds, (_, rect)| bounds.union(*rect));
+        self.min = Self::cell_at(bounds.min) - BOUNDS_MARGIN;
+        self.size = Self::cell_at(bounds.max) + BOUNDS_MARGIN - self.min + 1;
+        let len = (self.size.x * self.size.y) as usize;
+        self.blocked = vec![false; len];
+        self.cost = vec![u32::MAX; len];
+
+        for (_, rect) in obstacles {
+            // cells whose inside overlaps the rectangle, touching edges don't count
+            let half = CELL_SIZE / 2.;
+            let min = ((rect.min - half) / CELL_SIZE).floor().as_ivec2() + 1;
+            let max = ((rect.max + half) / CELL_SIZE).ceil().as_ivec2() - 1;
+            for y in min.y..=max.y {
+                for x in min.x..=max.x {
+                    if let Some(index) = self.index(IVec2::new(x, y)) {
+                        self.blocked[index] = true;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Dijkstra from `target` over the free cells. Diagonal steps are only
+    /// taken when both cells beside them are free, so corners aren't cut.
+    fn compute(&mut self, target: IVec2) {
+        self.target = Some(target);
+        self.cost.fill(u32::MAX);
+        let Some(start) = self.index(target) else {
+            return;
+        };
+        self.cost[start] = 0;
+        let mut queue = BinaryHeap::from([Reverse((0, target.x, target.y))]);
+        while let Some(Reverse((cost, x, y))) = queue.pop() {
+            let cell = IVec2::new(x, y);
+            if cost > self.cost(cell) {
+                continue;
+            }
+            for offset in NEIGHBOURS {
+                let next = cell + offset;
+                if !self.is_free(next) {
+                    continue;
+                }
+                let diagonal = offset.x != 0 && offset.y != 0;
+                if diagonal
+                    && !(self.is_free(cell + IVec2::new(offset.x, 0))
+                        && self.is_free(cell + IVec2::new(0, offset.y)))
+                {
+                    continue;
+                }
+                let next_cost = cost
+                    + if diagonal {
+                        DIAGONAL_COST
+                    } else {
+                        STRAIGHT_COST
+                    };
+                let index = self.index(next).unwrap();
+                if next_cost < self.cost[index] {
+                    self.cost[index] = next_cost;
+                    queue.push(Reverse((next_cost, next.x, next.y)));
+                }
+            }
+        }
+    }
+
+    /// Where an enemy at `position` should head next: the center of the
+    /// neighbouring cell closest to the player. `None` when the field can't
+    /// help, like outside the grid or in the player's own cell.
+    pub fn next_waypoint(&self, position: Vec2) -> Option<Vec2> {
+        let cell = Self::cell_at(position);
+        let cost = self.cost(cell);
+        if cost == 0 || cost == u32::MAX {
+            return None;
+        }
+        NEIGHBOURS
+            .iter()
+            .map(|offset| cell + *offset)
+            .filter(|next| self.cost(*next) < cost)
+            .min_by_key(|next| self.cost(*next))
+            .map(|next| next.as_vec2() * CELL_SIZE)
+    }
+}
+
+pub struct NavigationPlugin;
+
+impl Plugin for NavigationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FlowField>().add_systems(
+            (
+                rebuild_flow_field.run_if(in_state(AppState::Playing)),
+                update_flow_field.run_if(in_state(AppState::Playing)),
+            )
+                .chain()
+                // new walls only have a correct `GlobalTransform` after propagation
+                .in_base_set(CoreSet::PostUpdate)
+                .after(TransformSystem::TransformPropagate),
+        );
+    }
+}
+
+/// Rebuilds the grid whenever a static collider is added or removed.
+pub fn rebuild_flow_field(
+    mut flow_field: ResMut<FlowField>,
+    added_query: Query<&RigidBody, Added<Collider>>,
+    mut removed: RemovedComponents<Collider>,
+    query: Query<(Entity, &RigidBody, &Collider, &GlobalTransform)>,
+) {
+    let added = added_query.iter().any(|body| *body == RigidBody::Fixed);
+    let removed = removed
+        .iter()
+        .any(|entity| flow_field.obstacles.contains(&entity));
+    if !added && !removed {
+        return;
+    }
+    let obstacles = query
+        .iter()
+        .filter(|(_, body, _, _)| **body == RigidBody::Fixed)
+        .map(|(entity, _, collider, transform)| {
+            let (_, rotation, translation) = transform.to_scale_rotation_translation();
+            let (angle, _, _) = rotation.to_euler(EulerRot::ZYX);
+            let isometry =
+                na::Isometry2::new(na::Vector2::new(translation.x, translation.y), angle);
+            let aabb = collider.raw.compute_aabb(&isometry);
+            (
+                entity,
+                Rect::new(aabb.mins.x, aabb.mins.y, aabb.maxs.x, aabb.maxs.y),
+            )
+        })
+        .collect();
+    flow_field.rebuild(obstacles);
+}
+
+pub fn update_flow_field(
+    mut flow_field: ResMut<FlowField>,
+    player_query: Query<&Transform, With<Player>>,
+) {
+    let Ok(player_transform) = player_query.get_single() else {
+        return;
+    };
+    let cell = FlowField::cell_at(player_transform.translation.truncate());
+    if flow_field.target != Some(cell) {
+        flow_field.compute(cell);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A field with the given cells blocked, solved towards `target`.
+    fn solved(walls: impl IntoIterator<Item = IVec2>, target: IVec2) -> FlowField {
+        let obstacles = walls
+            .into_iter()
+            .enumerate()
+            .map(|(index, cell)| {
+                let rect =
+                    Rect::from_center_size(cell.as_vec2() * CELL_SIZE, Vec2::splat(CELL_SIZE));
+                (Entity::from_raw(index as u32), rect)
+            })
+            .collect();
+        let mut flow_field = FlowField::default();
+        flow_field.rebuild(obstacles);
+        flow_field.compute(target);
+        flow_field
+    }
+
+    #[test]
+    fn distances_route_around_a_wall() {
+        let wall = (-3..=3).map(|y| IVec2::new(0, y));
+        let flow_field = solved(wall, IVec2::new(-2, 0));
+        // around the end of the wall instead of the 4 cells straight through
+        // it: a diagonal run to the wall's end, two steps past it and back
+        let around = 2 * (DIAGONAL_COST + 3 * STRAIGHT_COST) + 2 * STRAIGHT_COST;
+        assert_eq!(flow_field.cost(IVec2::new(2, 0)), around);
+        assert_eq!(flow_field.cost(IVec2::new(0, 0)), u32::MAX);
+
+        let waypoint = flow_field
+            .next_waypoint(IVec2::new(2, 0).as_vec2() * CELL_SIZE)
+            .unwrap();
+        assert!(flow_field.is_free(FlowField::cell_at(waypoint)));
+    }
+
+    #[test]
+    fn enclosed_cells_stay_unreachable() {
+        let walls = NEIGHBOURS.iter().map(|offset| IVec2::new(0, 0) + *offset);
+        let flow_field = solved(walls, IVec2::new(3, 0));
+        assert_eq!(flow_field.cost(IVec2::new(0, 0)), u32::MAX);
+        assert_eq!(flow_field.next_waypoint(Vec2::ZERO), None);
+        assert!(flow_field.cost(IVec2::new(2, 2)) < u32::MAX);
+    }
+}
@@ -5,9 +5,25 @@ use bevy::{
 };
 use bevy_rapier2d::prelude::*;
 
-use crate::{combat::Health, sprite_sheets::SpriteSheetsMaps};
+use crate::{
+    combat::{
+        ContactDamage, Health, Invulnerability, ENEMY_GROUP, ENEMY_PROJECTILE_GROUP, PLAYER_GROUP,
+        PLAYER_HITBOX_GROUP,
+    },
+    controls::{first_gamepad, stick, AimMode, Controls, MovementScheme, SPELL_BUTTONS},
+    enemy::EnemySpatialHash,
+    experience::Experience,
+    game_state::AppState,
+    spell::{Mana, SpellSlot, Spellbook},
+    spell_definition::SpellDefinition,
+    sprite_sheets::{tile_index, SpriteSheetsMaps},
+    stats::{Stat, Stats},
+    weapon::{Weapon, WeaponKind, Weapons, TARGET_RANGE},
+};
 
 const PLAYER_SIZE: f32 = 32.;
+/// Seconds the player can't be hurt after taking a hit.
+const INVULNERABILITY_DURATION: f32 = 0.6;
 
 #[derive(Component)]
 pub struct Cursor {
@@ -19,17 +35,31 @@ pub struct Player {
     pub destination: Vec3,
 }
 
+/// Sensor child of the player that takes hits from enemies and their
+/// projectiles. It is smaller than the sprite so grazing shots miss.
+#[derive(Component)]
+pub struct PlayerHitbox;
+
 pub struct PlayerPlugin;
 
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
-        app.add_startup_system(spawn_player)
-            .add_startup_system(spawn_cursor)
+        app.add_startup_system(spawn_cursor)
             .add_startup_system(cursor_grab_system)
             .add_event::<SpellEvent>()
-            .add_system(setup_player_movement)
-            .add_system(handle_player_movement)
-            .add_system(setup_player_spells)
+            .add_system(
+                spawn_player
+                    .run_if(not(any_with_component::<Player>()))
+                    .in_schedule(OnEnter(AppState::Playing)),
+            )
+            .add_systems(
+                (
+                    setup_player_movement,
+                    handle_player_movement,
+                    setup_player_spells,
+                )
+                    .in_set(OnUpdate(AppState::Playing)),
+            )
             .add_system(mouse_motion);
     }
 }
@@ -57,75 +87,225 @@ fn spawn_player(
     mut commands: Commands,
     query: Query<&Window, With<PrimaryWindow>>,
     sprite_sheets_maps: Res<SpriteSheetsMaps>,
+    asset_server: Res<AssetServer>,
+    controls: Res<Controls>,
 ) {
     let window = query.get_single().unwrap();
-    let player_index = (8 - 1) * 12 + (1 - 1);
-    commands.spawn((
-        RigidBody::KinematicVelocityBased,
-        Collider::cuboid(PLAYER_SIZE / 2., PLAYER_SIZE / 2.),
-        Velocity { ..default() },
-        SpriteSheetBundle {
-            texture_atlas: sprite_sheets_maps.characters_atlas.clone(),
-            sprite: TextureAtlasSprite {
-                index: player_index,
-                custom_size: Some(Vec2::splat(PLAYER_SIZE)),
+    let spells = [
+        "spells/fireball.spell.ron",
+        "spells/spark.spell.ron",
+        "spells/flame_orb.spell.ron",
+        "spells/inferno.spell.ron",
+    ];
+    let translation = Vec3::new(window.width() / 2., window.height() / 2., 0.);
+    let stats = Stats::new([
+        (Stat::MaxHealth, 10.),
+        (Stat::MoveSpeed, 120.),
+        (Stat::Damage, 1.),
+        (Stat::ProjectileSpeed, 1.),
+        (Stat::ProjectileCount, 1.),
+        (Stat::Cooldown, 1.),
+        (Stat::CritChance, 0.05),
+    ]);
+    let max_health = stats.max_health();
+    let player_index = tile_index(8, 1);
+    commands
+        .spawn((
+            RigidBody::KinematicVelocityBased,
+            Collider::cuboid(PLAYER_SIZE / 2., PLAYER_SIZE / 2.),
+            CollisionGroups::new(PLAYER_GROUP, Group::NONE),
+            Velocity { ..default() },
+            SpriteSheetBundle {
+                texture_atlas: sprite_sheets_maps.characters_atlas.clone(),
+                sprite: TextureAtlasSprite {
+                    index: player_index,
+                    custom_size: Some(Vec2::splat(PLAYER_SIZE)),
+                    ..default()
+                },
+                transform: Transform::from_translation(translation),
                 ..default()
             },
-            transform: Transform::from_xyz(window.width() / 2., window.height() / 2., 0.),
-            ..default()
-        },
-        Player {
-            destination: Vec3::ZERO,
-        },
-        Health {
-            total: 10,
-            current: 10,
-        },
-    ));
+            Player {
+                destination: translation,
+            },
+            Health {
+                total: max_health,
+                current: max_health,
+            },
+            Invulnerability::new(INVULNERABILITY_DURATION),
+            ContactDamage::default(),
+            stats,
+            Mana {
+                total: 100.,
+                current: 100.,
+                regen: 5.,
+            },
+            Spellbook {
+                slots: controls
+                    .movement
+                    .spell_keys()
+                    .into_iter()
+                    .zip(SPELL_BUTTONS)
+                    .zip(spells)
+                    .map(|((key, button), spell)| {
+                        SpellSlot::new(key, button, asset_server.load(spell))
+                    })
+                    .collect(),
+            },
+            Weapons {
+                weapons: vec![Weapon::new(WeaponKind::MagicMissile, &asset_server)],
+            },
+            Experience::default(),
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                Collider::ball(PLAYER_SIZE * 0.3),
+                Sensor,
+                ActiveEvents::COLLISION_EVENTS,
+                ActiveCollisionTypes::default() | ActiveCollisionTypes::KINEMATIC_KINEMATIC,
+                CollisionGroups::new(PLAYER_HITBOX_GROUP, ENEMY_GROUP | ENEMY_PROJECTILE_GROUP),
+                TransformBundle::default(),
+                PlayerHitbox,
+            ));
+        });
 }
 
 fn setup_player_movement(
-    mut controllers: Query<(&mut Velocity, &Transform, &mut Player)>,
+    mut controllers: Query<&mut Player>,
     mouse_input: Res<Input<MouseButton>>,
     query: Query<&Cursor>,
+    controls: Res<Controls>,
 ) {
-    if let Ok((mut velocity, transform, mut player)) = controllers.get_single_mut() {
+    if controls.movement != MovementScheme::ClickToMove {
+        return;
+    }
+    if let Ok(mut player) = controllers.get_single_mut() {
         if mouse_input.just_pressed(MouseButton::Right) {
-            let cursor_translation = query.single().translation;
-            player.destination = cursor_translation;
-            let direction = Vec2::new(cursor_translation.x, cursor_translation.y)
-                - Vec2::new(transform.translation.x, transform.translation.y);
-            velocity.linvel = direction.normalize() * 120.;
+            player.destination = query.single().translation;
         }
     }
 }
 
-fn handle_player_movement(mut query: Query<(&mut Velocity, &Transform, &Player)>) {
-    if let Ok((mut velocity, transform, player)) = query.get_single_mut() {
-        let distance = transform.translation.distance(player.destination);
-        if distance < 1. {
-            velocity.linvel = Vec2::ZERO;
+/// Walks towards the destination, or wherever the keys or stick point. The
+/// velocity is set every frame because walls can take part of it away.
+fn handle_player_movement(
+    mut query: Query<(&mut Velocity, &Transform, &Player, &Stats)>,
+    controls: Res<Controls>,
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    axes: Res<Axis<GamepadAxis>>,
+    time: Res<Time>,
+) {
+    let Ok((mut velocity, transform, player, stats)) = query.get_single_mut() else {
+        return;
+    };
+    let speed = stats.get(Stat::MoveSpeed);
+    velocity.linvel = match controls.movement {
+        MovementScheme::ClickToMove => {
+            let direction = (player.destination - transform.translation).truncate();
+            // stop instead of overshooting when the destination is less than a frame away
+            if direction.length() <= speed * time.delta_seconds() {
+                Vec2::ZERO
+            } else {
+                direction.normalize() * speed
+            }
         }
-    }
+        MovementScheme::Keyboard => {
+            let pressed = |keys: [KeyCode; 2]| keyboard_input.any_pressed(keys) as i32 as f32;
+            let direction = Vec2::new(
+                pressed([KeyCode::D, KeyCode::Right]) - pressed([KeyCode::A, KeyCode::Left]),
+                pressed([KeyCode::W, KeyCode::Up]) - pressed([KeyCode::S, KeyCode::Down]),
+            );
+            direction.normalize_or_zero() * speed
+        }
+        MovementScheme::Gamepad => first_gamepad(&gamepads)
+            .and_then(|gamepad| {
+                stick(
+                    &axes,
+                    gamepad,
+                    GamepadAxisType::LeftStickX,
+                    GamepadAxisType::LeftStickY,
+                )
+            })
+            .map_or(Vec2::ZERO, |position| position.clamp_length_max(1.) * speed),
+    };
 }
 
 pub struct SpellEvent {
     pub direction: Vec2,
+    pub spell: Handle<SpellDefinition>,
 }
 
+#[allow(clippy::too_many_arguments)]
 fn setup_player_spells(
     keyboard_input: Res<Input<KeyCode>>,
+    gamepad_input: Res<Input<GamepadButton>>,
+    gamepads: Res<Gamepads>,
+    axes: Res<Axis<GamepadAxis>>,
+    controls: Res<Controls>,
     mut spell_event: EventWriter<SpellEvent>,
-    controllers: Query<&Transform, With<Player>>,
+    mut controllers: Query<(&Transform, &mut Spellbook, &mut Mana, &Stats), With<Player>>,
     query: Query<&Transform, With<Cursor>>,
+    spatial_hash: Res<EnemySpatialHash>,
+    spell_definitions: Res<Assets<SpellDefinition>>,
+    time: Res<Time>,
 ) {
-    if keyboard_input.just_pressed(KeyCode::Q) {
-        let player_translation = controllers.single().translation;
-        let cursor_translation = query.single().translation;
-        let direction = Vec2::new(cursor_translation.x - 8., cursor_translation.y + 8.)
-            - Vec2::new(player_translation.x, player_translation.y);
+    let (player_transform, mut spellbook, mut mana, stats) = controllers.single_mut();
+    let gamepad = first_gamepad(&gamepads);
+    let player_translation = player_transform.translation;
+    let nearest_enemy = || {
+        spatial_hash
+            .nearest(player_translation.truncate(), TARGET_RANGE)
+            .map(|(_, position, _)| *position - player_translation.truncate())
+    };
+    for slot in spellbook.slots.iter_mut() {
+        slot.cooldown.tick(time.delta());
+        let pressed = keyboard_input.just_pressed(slot.key)
+            || gamepad.is_some_and(|gamepad| {
+                gamepad_input.just_pressed(GamepadButton::new(gamepad, slot.button))
+            });
+        if !pressed || !slot.cooldown.finished() {
+            continue;
+        }
+        let direction = match controls.aim {
+            AimMode::Cursor => {
+                let cursor_translation = query.single().translation;
+                Some(
+                    Vec2::new(cursor_translation.x - 8., cursor_translation.y + 8.)
+                        - Vec2::new(player_translation.x, player_translation.y),
+                )
+            }
+            AimMode::RightStick => gamepad
+                .and_then(|gamepad| {
+                    stick(
+                        &axes,
+                        gamepad,
+                        GamepadAxisType::RightStickX,
+                        GamepadAxisType::RightStickY,
+                    )
+                })
+                .or_else(nearest_enemy),
+            AimMode::Auto => nearest_enemy(),
+        };
+        // nothing to aim at
+        let Some(direction) = direction.filter(|direction| *direction != Vec2::ZERO) else {
+            continue;
+        };
+        let Some(definition) = spell_definitions.get(&slot.spell) else {
+            continue;
+        };
+        if mana.current < definition.mana_cost {
+            continue;
+        }
+        mana.current -= definition.mana_cost;
+        slot.cooldown = Timer::from_seconds(
+            definition.cooldown * stats.get(Stat::Cooldown),
+            TimerMode::Once,
+        );
+
         spell_event.send(SpellEvent {
             direction: direction.normalize(),
+            spell: slot.spell.clone(),
         });
     }
 }
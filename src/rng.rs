@@ -0,0 +1,208 @@
+use bevy::prelude::*;
+use rand::{random, rngs::StdRng, SeedableRng};
+
+use crate::{game_state::AppState, utils::arg_value};
+
+/// The single source of gameplay randomness. Spawns, drops and upgrade rolls
+/// all draw from it, so running with the same seed replays the same run.
+#[derive(Resource, Deref, DerefMut)]
+pub struct GameRng(StdRng);
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        GameRng(StdRng::seed_from_u64(seed))
+    }
+}
+
+/// Every system that draws from `GameRng` while playing goes in one of these.
+/// They run in this order, so the draws happen in the same order each frame
+/// and a seed always replays the same way.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum RngSet {
+    Dungeon,
+    Spawns,
+    Weapons,
+    Hits,
+}
+
+#[derive(Resource)]
+pub struct Seed(pub u64);
+
+impl Seed {
+    /// Reads the seed from `--seed <n>`, or picks a random one.
+    fn from_args() -> Self {
+        let seed = arg_value("--seed").and_then(|value| match value.parse() {
+            Ok(seed) => Some(seed),
+            Err(_) => {
+                warn!("invalid --seed value {value:?}, using a random seed");
+                None
+            }
+        });
+        Seed(seed.unwrap_or_else(random))
+    }
+}
+
+pub struct RngPlugin;
+
+impl Plugin for RngPlugin {
+    fn build(&self, app: &mut App) {
+        let seed = Seed::from_args();
+        info!("seed: {}", seed.0);
+        app.insert_resource(GameRng::new(seed.0))
+            .insert_resource(seed)
+            .configure_sets(
+                (
+                    RngSet::Dungeon,
+                    RngSet::Spawns,
+                    RngSet::Weapons,
+                    RngSet::Hits,
+                )
+                    .chain(),
+            )
+            // every run starts from the same seed, not from wherever the last run left off
+            .add_system(reseed.in_schedule(OnExit(AppState::GameOver)));
+    }
+}
+
+fn reseed(mut rng: ResMut<GameRng>, seed: Res<Seed>) {
+    *rng = GameRng::new(seed.0);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use bevy::{asset::AssetPlugin, core::TaskPoolPlugin};
+    use bevy_rapier2d::{
+        prelude::{CollisionEvent, RapierContext},
+        rapier::geometry::CollisionEventFlags,
+    };
+    use rand::seq::SliceRandom;
+
+    use super::*;
+    use crate::{
+        combat::{apply_damage, CombatSet, DamageEvent, DamageType, DeathEvent, Health},
+        dungeon::DungeonMap,
+        enemy::{despawn_dead_enemies, setup_enemy_registry, Enemy},
+        player::Player,
+        spell::{handle_particle_contacts, Spell},
+        sprite_sheets::SpriteSheetsMaps,
+        wave::WavePlugin,
+        wave_schedule::WaveSchedule,
+        Score,
+    };
+
+    const FRAMES: usize = 200;
+    /// Long frames, so a few waves go by quickly.
+    const FRAME_TIME: Duration = Duration::from_millis(500);
+    const HITS_PER_FRAME: usize = 20;
+
+    /// Plays the start of a run headless, with the wave spawner and the spell
+    /// hits both drawing from the game's rng, and returns where every enemy
+    /// ended up, their health and the score.
+    fn play(seed: u64) -> (Vec<(Vec3, usize)>, u32, u32) {
+        let mut app = App::new();
+        app.add_plugin(TaskPoolPlugin::default())
+            .add_plugin(AssetPlugin::default())
+            .add_state::<AppState>()
+            .add_plugin(RngPlugin)
+            .add_plugin(WavePlugin)
+            .add_asset::<DungeonMap>()
+            .insert_resource(GameRng::new(seed))
+            .insert_resource(RapierContext::default())
+            .insert_resource(SpriteSheetsMaps {
+                characters_atlas: Handle::default(),
+            })
+            .init_resource::<Time>()
+            .init_resource::<Score>()
+            .add_event::<CollisionEvent>()
+            .add_event::<DamageEvent>()
+            .add_event::<DeathEvent>()
+            .add_startup_system(setup_enemy_registry)
+            .add_systems(
+                (
+                    handle_particle_contacts
+                        .in_set(RngSet::Hits)
+                        .before(CombatSet::ResolveDamage),
+                    apply_damage.in_set(CombatSet::ResolveDamage),
+                    despawn_dead_enemies.after(CombatSet::ResolveDamage),
+                )
+                    .in_set(OnUpdate(AppState::Playing)),
+            );
+        app.world
+            .spawn((Camera2d::default(), OrthographicProjection::default()));
+        app.world.spawn((
+            Transform::default(),
+            Player {
+                destination: Vec3::ZERO,
+            },
+        ));
+        let spells: Vec<Entity> = (0..10)
+            .map(|_| {
+                let spell = Spell::new(1, DamageType::Fire)
+                    .with_pierce(u32::MAX)
+                    .with_crit_chance(0.5);
+                app.world.spawn(spell).id()
+            })
+            .collect();
+        app.world
+            .resource_mut::<NextState<AppState>>()
+            .set(AppState::Playing);
+
+        // the clock stands still until the wave schedule has loaded
+        while app.world.resource::<Assets<WaveSchedule>>().is_empty() {
+            app.update();
+        }
+
+        // picks the hits, the same way in both runs
+        let mut hits = StdRng::seed_from_u64(0);
+        let mut now = Instant::now();
+        for _ in 0..FRAMES {
+            now += FRAME_TIME;
+            app.world.resource_mut::<Time>().update_with_instant(now);
+            let mut enemies: Vec<Entity> = app
+                .world
+                .query_filtered::<Entity, With<Enemy>>()
+                .iter(&app.world)
+                .collect();
+            enemies.sort();
+            if !enemies.is_empty() {
+                let mut collision_events = app.world.resource_mut::<Events<CollisionEvent>>();
+                for _ in 0..HITS_PER_FRAME {
+                    collision_events.send(CollisionEvent::Started(
+                        *spells.choose(&mut hits).unwrap(),
+                        *enemies.choose(&mut hits).unwrap(),
+                        CollisionEventFlags::empty(),
+                    ));
+                }
+            }
+            app.update();
+        }
+
+        let mut enemies: Vec<(Entity, Vec3, usize)> = app
+            .world
+            .query_filtered::<(Entity, &Transform, &Health), With<Enemy>>()
+            .iter(&app.world)
+            .map(|(entity, transform, health)| (entity, transform.translation, health.current))
+            .collect();
+        enemies.sort_by_key(|(entity, ..)| *entity);
+        let score = app.world.resource::<Score>();
+        (
+            enemies
+                .into_iter()
+                .map(|(_, translation, health)| (translation, health))
+                .collect(),
+            score.value,
+            score.kills,
+        )
+    }
+
+    #[test]
+    fn same_seed_replays_the_same_run() {
+        let first = play(3);
+        assert!(!first.0.is_empty(), "no enemies spawned");
+        assert!(first.2 > 0, "no enemies killed");
+        assert_eq!(first, play(3));
+        assert_ne!(first, play(4));
+    }
+}
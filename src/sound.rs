@@ -0,0 +1,349 @@
+use bevy::{prelude::*, utils::HashMap};
+use rand::Rng;
+
+use crate::{
+    combat::{CombatSet, DamageEvent, DeathEvent, Health},
+    enemy::Enemy,
+    game_state::{despawn_screen, settings_panel, spawn_button, AppState},
+    player::{Player, SpellEvent},
+    utils::arg_value,
+};
+
+/// Every sound plays this much higher or lower at random, so repeats don't
+/// sound mechanical.
+const PITCH_VARIATION: f32 = 0.1;
+/// How much the pause menu's volume buttons change a volume by.
+const VOLUME_STEP: f32 = 0.1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SoundEffect {
+    SpellCast,
+    EnemyHit,
+    EnemyDeath,
+    PlayerHurt,
+    LevelUp,
+}
+
+struct Sound {
+    path: &'static str,
+    volume: f32,
+    pitch: f32,
+    /// Length of the clip at normal pitch, for knowing when a copy is done.
+    duration: f32,
+    /// Copies allowed to play at once. Extra ones are dropped.
+    max_instances: usize,
+}
+
+impl SoundEffect {
+    const ALL: [SoundEffect; 5] = [
+        SoundEffect::SpellCast,
+        SoundEffect::EnemyHit,
+        SoundEffect::EnemyDeath,
+        SoundEffect::PlayerHurt,
+        SoundEffect::LevelUp,
+    ];
+
+    fn sound(&self) -> Sound {
+        match self {
+            SoundEffect::SpellCast => Sound {
+                path: "audio/pluck_001.ogg",
+                volume: 0.6,
+                pitch: 1.2,
+                duration: 0.1,
+                max_instances: 3,
+            },
+            SoundEffect::EnemyHit => Sound {
+                path: "audio/pluck_002.ogg",
+                volume: 0.4,
+                pitch: 1.,
+                duration: 0.16,
+                max_instances: 4,
+            },
+            SoundEffect::EnemyDeath => Sound {
+                path: "audio/explosionCrunch_000.ogg",
+                volume: 0.3,
+                pitch: 1.4,
+                duration: 0.78,
+                max_instances: 4,
+            },
+            SoundEffect::PlayerHurt => Sound {
+                path: "audio/explosionCrunch_000.ogg",
+                volume: 0.8,
+                pitch: 0.7,
+                duration: 0.78,
+                max_instances: 1,
+            },
+            SoundEffect::LevelUp => Sound {
+                path: "audio/pluck_001.ogg",
+                volume: 1.,
+                pitch: 0.6,
+                duration: 0.1,
+                max_instances: 1,
+            },
+        }
+    }
+}
+
+/// Volume settings, each from 0 to 1. Sound effects play at `master * sfx`
+/// and music at `master * music`. They can be changed from the pause menu.
+#[derive(Resource)]
+pub struct Volume {
+    pub master: f32,
+    pub sfx: f32,
+    pub music: f32,
+}
+
+impl Volume {
+    /// Reads the starting volumes from `--volume`, `--sfx-volume` and
+    /// `--music-volume`.
+    fn from_args() -> Self {
+        let volume = |name: &str, default: f32| {
+            arg_value(name).map_or(default, |value| match value.parse::<f32>() {
+                Ok(volume) => volume.clamp(0., 1.),
+                Err(_) => {
+                    warn!("invalid {name} value {value:?}, using {default}");
+                    default
+                }
+            })
+        };
+        Volume {
+            master: volume("--volume", 1.),
+            sfx: volume("--sfx-volume", 1.),
+            music: volume("--music-volume", 0.6),
+        }
+    }
+
+    pub fn sfx(&self) -> f32 {
+        self.master * self.sfx
+    }
+
+    pub fn music(&self) -> f32 {
+        self.master * self.music
+    }
+
+    fn channel(&self, channel: VolumeChannel) -> f32 {
+        match channel {
+            VolumeChannel::Master => self.master,
+            VolumeChannel::Sfx => self.sfx,
+            VolumeChannel::Music => self.music,
+        }
+    }
+
+    fn channel_mut(&mut self, channel: VolumeChannel) -> &mut f32 {
+        match channel {
+            VolumeChannel::Master => &mut self.master,
+            VolumeChannel::Sfx => &mut self.sfx,
+            VolumeChannel::Music => &mut self.music,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum VolumeChannel {
+    Master,
+    Sfx,
+    Music,
+}
+
+impl VolumeChannel {
+    const ALL: [VolumeChannel; 3] = [
+        VolumeChannel::Master,
+        VolumeChannel::Sfx,
+        VolumeChannel::Music,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            VolumeChannel::Master => "Volume",
+            VolumeChannel::Sfx => "Effects",
+            VolumeChannel::Music => "Music",
+        }
+    }
+}
+
+/// The pause menu's volume controls.
+#[derive(Component)]
+struct VolumeMenu;
+
+/// Turns `channel` up or down by `VOLUME_STEP`.
+#[derive(Component, Clone, Copy)]
+struct VolumeButton {
+    channel: VolumeChannel,
+    up: bool,
+}
+
+/// Shows the current volume of a channel, between its two buttons.
+#[derive(Component)]
+struct VolumeLabel(VolumeChannel);
+
+/// Asks for a sound effect to be played. Anything can send one; the pitch,
+/// volume and limit on copies are taken care of by `play_sounds`.
+pub struct PlaySound(pub SoundEffect);
+
+#[derive(Resource)]
+struct SoundEffects {
+    handles: HashMap<SoundEffect, Handle<AudioSource>>,
+    /// When each copy that is still playing ends, in seconds since startup.
+    playing: HashMap<SoundEffect, Vec<f32>>,
+}
+
+pub struct SoundPlugin;
+
+impl Plugin for SoundPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Volume::from_args())
+            .add_event::<PlaySound>()
+            .add_startup_system(load_sound_effects)
+            .add_system(play_sounds)
+            .add_system(level_up_sound.in_schedule(OnEnter(AppState::LevelUp)))
+            .add_system(spawn_volume_menu.in_schedule(OnEnter(AppState::Paused)))
+            .add_system(despawn_screen::<VolumeMenu>.in_schedule(OnExit(AppState::Paused)))
+            .add_system(change_volume.in_set(OnUpdate(AppState::Paused)))
+            .add_systems(
+                (
+                    gameplay_sounds.after(CombatSet::ResolveDamage),
+                    player_hurt_sound,
+                )
+                    .in_set(OnUpdate(AppState::Playing)),
+            );
+    }
+}
+
+fn load_sound_effects(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let handles = SoundEffect::ALL
+        .into_iter()
+        .map(|effect| (effect, asset_server.load(effect.sound().path)))
+        .collect();
+    commands.insert_resource(SoundEffects {
+        handles,
+        playing: HashMap::default(),
+    });
+}
+
+fn volume_label(volume: &Volume, channel: VolumeChannel) -> String {
+    format!("{} {:.0}%", channel.name(), volume.channel(channel) * 100.)
+}
+
+fn spawn_volume_menu(mut commands: Commands, asset_server: Res<AssetServer>, volume: Res<Volume>) {
+    let font = asset_server.load("fonts/DMSans-Regular.ttf");
+    let style = TextStyle {
+        font: font.clone(),
+        font_size: 22.,
+        color: Color::WHITE,
+    };
+    commands
+        .spawn((settings_panel(), VolumeMenu))
+        .with_children(|parent| {
+            for channel in VolumeChannel::ALL {
+                spawn_button(
+                    parent,
+                    &font,
+                    "-".to_string(),
+                    VolumeButton { channel, up: false },
+                );
+                parent.spawn((
+                    TextBundle::from_section(volume_label(&volume, channel), style.clone()),
+                    VolumeLabel(channel),
+                ));
+                spawn_button(
+                    parent,
+                    &font,
+                    "+".to_string(),
+                    VolumeButton { channel, up: true },
+                );
+            }
+        });
+}
+
+fn change_volume(
+    button_query: Query<(&Interaction, &VolumeButton), Changed<Interaction>>,
+    mut label_query: Query<(&mut Text, &VolumeLabel)>,
+    mut volume: ResMut<Volume>,
+) {
+    for (interaction, button) in button_query.iter() {
+        if *interaction != Interaction::Clicked {
+            continue;
+        }
+        let step = if button.up { VOLUME_STEP } else { -VOLUME_STEP };
+        let value = volume.channel_mut(button.channel);
+        // rounded to whole steps, so repeated clicks don't drift
+        *value = ((*value + step) / VOLUME_STEP).round() * VOLUME_STEP;
+        *value = value.clamp(0., 1.);
+        for (mut text, label) in label_query.iter_mut() {
+            if label.0 == button.channel {
+                text.sections[0].value = volume_label(&volume, button.channel);
+            }
+        }
+    }
+}
+
+fn play_sounds(
+    mut events: EventReader<PlaySound>,
+    mut sound_effects: ResMut<SoundEffects>,
+    audio: Res<Audio>,
+    volume: Res<Volume>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_seconds();
+    let SoundEffects { handles, playing } = &mut *sound_effects;
+    for PlaySound(effect) in events.iter() {
+        let sound = effect.sound();
+        let instances = playing.entry(*effect).or_default();
+        instances.retain(|end| *end > now);
+        if instances.len() >= sound.max_instances {
+            continue;
+        }
+        // not the game's rng, sounds must not change what happens in a seeded run
+        let pitch =
+            sound.pitch * (1. + rand::thread_rng().gen_range(-PITCH_VARIATION..PITCH_VARIATION));
+        instances.push(now + sound.duration / pitch);
+        audio.play_with_settings(
+            handles[effect].clone(),
+            PlaybackSettings::ONCE
+                .with_volume(sound.volume * volume.sfx())
+                .with_speed(pitch),
+        );
+    }
+}
+
+fn gameplay_sounds(
+    mut spell_events: EventReader<SpellEvent>,
+    mut damage_events: EventReader<DamageEvent>,
+    mut death_events: EventReader<DeathEvent>,
+    mut sounds: EventWriter<PlaySound>,
+    enemy_query: Query<(), With<Enemy>>,
+) {
+    for _ in spell_events.iter() {
+        sounds.send(PlaySound(SoundEffect::SpellCast));
+    }
+    for damage_event in damage_events.iter() {
+        if enemy_query.contains(damage_event.target) {
+            sounds.send(PlaySound(SoundEffect::EnemyHit));
+        }
+    }
+    for death_event in death_events.iter() {
+        if enemy_query.contains(death_event.entity) {
+            sounds.send(PlaySound(SoundEffect::EnemyDeath));
+        }
+    }
+}
+
+/// Plays when the player's health goes down, so hits blocked by
+/// invulnerability stay silent.
+fn player_hurt_sound(
+    mut sounds: EventWriter<PlaySound>,
+    query: Query<&Health, (With<Player>, Changed<Health>)>,
+    mut last_health: Local<usize>,
+) {
+    let Ok(health) = query.get_single() else {
+        return;
+    };
+    if health.current < *last_health {
+        sounds.send(PlaySound(SoundEffect::PlayerHurt));
+    }
+    *last_health = health.current;
+}
+
+fn level_up_sound(mut sounds: EventWriter<PlaySound>) {
+    sounds.send(PlaySound(SoundEffect::LevelUp));
+}
@@ -0,0 +1,111 @@
+use bevy::{prelude::*, utils::HashMap};
+
+/// Entities bucketed by the grid cell they are in, for cheap "what is near
+/// this point" queries. Rebuilt from scratch every frame.
+pub struct SpatialHash {
+    cell_size: f32,
+    cells: HashMap<IVec2, Vec<(Entity, Vec2, f32)>>,
+    /// Largest radius inserted since the last clear.
+    pub max_radius: f32,
+}
+
+impl SpatialHash {
+    pub fn new(cell_size: f32) -> Self {
+        SpatialHash {
+            cell_size,
+            cells: HashMap::default(),
+            max_radius: 0.,
+        }
+    }
+
+    fn cell(&self, point: Vec2) -> IVec2 {
+        (point / self.cell_size).floor().as_ivec2()
+    }
+
+    /// Empties every bucket but keeps their allocations for the next frame.
+    pub fn clear(&mut self) {
+        for entries in self.cells.values_mut() {
+            entries.clear();
+        }
+        self.max_radius = 0.;
+    }
+
+    pub fn insert(&mut self, entity: Entity, position: Vec2, radius: f32) {
+        let cell = self.cell(position);
+        self.max_radius = self.max_radius.max(radius);
+        self.cells
+            .entry(cell)
+            .or_default()
+            .push((entity, position, radius));
+    }
+
+    /// Entities whose cell overlaps the square around `center`. Callers still
+    /// need to check the actual distance.
+    pub fn nearby(
+        &self,
+        center: Vec2,
+        distance: f32,
+    ) -> impl Iterator<Item = &(Entity, Vec2, f32)> + '_ {
+        let min = self.cell(center - distance);
+        let max = self.cell(center + distance);
+        (min.y..=max.y)
+            .flat_map(move |y| (min.x..=max.x).map(move |x| IVec2::new(x, y)))
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+    }
+
+    /// The entity closest to `center`, searching a growing square until one is
+    /// found or `max_distance` is reached.
+    pub fn nearest(&self, center: Vec2, max_distance: f32) -> Option<&(Entity, Vec2, f32)> {
+        let mut distance = self.cell_size;
+        loop {
+            let nearest = self
+                .nearby(center, distance)
+                .map(|entry| (entry, entry.1.distance_squared(center)))
+                .filter(|(_, distance_squared)| *distance_squared <= distance * distance)
+                .min_by(|(_, a), (_, b)| a.total_cmp(b));
+            if let Some((entry, _)) = nearest {
+                return Some(entry);
+            }
+            if distance >= max_distance {
+                return None;
+            }
+            distance = (distance * 2.).min(max_distance);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(positions: &[Vec2]) -> SpatialHash {
+        let mut spatial_hash = SpatialHash::new(64.);
+        for (index, position) in positions.iter().enumerate() {
+            spatial_hash.insert(Entity::from_raw(index as u32), *position, 8.);
+        }
+        spatial_hash
+    }
+
+    #[test]
+    fn nearest_is_the_closest_not_the_first_found() {
+        // the second one shares a cell with the center, the first is closer
+        let spatial_hash = hash(&[Vec2::new(-20., 0.), Vec2::new(30., 30.)]);
+        let (entity, _, _) = spatial_hash.nearest(Vec2::new(1., 1.), 500.).unwrap();
+        assert_eq!(*entity, Entity::from_raw(0));
+    }
+
+    #[test]
+    fn nearest_looks_past_empty_cells() {
+        let spatial_hash = hash(&[Vec2::new(300., -200.), Vec2::new(-260., 260.)]);
+        let (entity, _, _) = spatial_hash.nearest(Vec2::ZERO, 500.).unwrap();
+        assert_eq!(*entity, Entity::from_raw(0));
+    }
+
+    #[test]
+    fn nearest_stops_at_the_max_distance() {
+        let spatial_hash = hash(&[Vec2::new(300., 0.)]);
+        assert!(spatial_hash.nearest(Vec2::ZERO, 200.).is_none());
+        assert!(SpatialHash::new(64.).nearest(Vec2::ZERO, 200.).is_none());
+    }
+}
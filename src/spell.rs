@@ -1,106 +1,235 @@
+use bevy::{prelude::*, utils::HashSet};
+use bevy_rapier2d::prelude::*;
+use rand::Rng;
+
 use crate::{
-    combat::{Damage, DamageEvent, Health},
+    combat::{CombatSet, DamageEvent, DamageType, ENEMY_GROUP, PLAYER_SPELL_GROUP, WALL_GROUP},
     enemy::Enemy,
+    game_state::AppState,
     player::{Player, SpellEvent},
-    sprite_sheets::{Animation, SpriteSheetsMaps},
-    Score,
+    rng::{GameRng, RngSet},
+    spell_definition::{SpellDefinition, SpellDefinitionLoader},
+    sprite_sheets::Animation,
+    stats::{Stat, Stats},
 };
-use bevy::prelude::*;
-use bevy_rapier2d::prelude::*;
+
+/// Angle between projectiles when a spell fires more than one.
+pub const SPREAD_ANGLE: f32 = 0.25;
+const CRIT_MULTIPLIER: usize = 2;
 
 #[derive(Component)]
 pub struct Spell {
     damage: usize,
+    damage_type: DamageType,
+    /// Enemies the projectile can still pass through before it is destroyed.
+    pierce: u32,
+    /// Chance from 0 to 1 for each hit to be critical.
+    crit_chance: f32,
+}
+
+impl Spell {
+    pub fn new(damage: usize, damage_type: DamageType) -> Self {
+        Spell {
+            damage,
+            damage_type,
+            pierce: 0,
+            crit_chance: 0.,
+        }
+    }
+
+    pub fn with_crit_chance(mut self, crit_chance: f32) -> Self {
+        self.crit_chance = crit_chance;
+        self
+    }
+
+    pub fn with_pierce(mut self, pierce: u32) -> Self {
+        self.pierce = pierce;
+        self
+    }
+}
+
+/// Despawns the entity once the timer runs out.
+#[derive(Component)]
+pub struct Lifetime(pub Timer);
+
+#[derive(Component)]
+pub struct Mana {
+    pub total: f32,
+    pub current: f32,
+    /// Mana regained per second.
+    pub regen: f32,
+}
+
+pub struct SpellSlot {
+    pub key: KeyCode,
+    pub button: GamepadButtonType,
+    pub spell: Handle<SpellDefinition>,
+    pub cooldown: Timer,
+}
+
+impl SpellSlot {
+    pub fn new(key: KeyCode, button: GamepadButtonType, spell: Handle<SpellDefinition>) -> Self {
+        SpellSlot {
+            key,
+            button,
+            spell,
+            cooldown: Timer::default(),
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct Spellbook {
+    pub slots: Vec<SpellSlot>,
 }
 
 pub struct SpellPlugin;
 
 impl Plugin for SpellPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system(shoot_particle)
-            .add_system(handle_particle_contacts);
+        app.add_asset::<SpellDefinition>()
+            .init_asset_loader::<SpellDefinitionLoader>()
+            .add_systems(
+                (
+                    regenerate_mana,
+                    shoot_particle,
+                    expire_particles,
+                    handle_particle_contacts
+                        .in_set(RngSet::Hits)
+                        .before(CombatSet::ResolveDamage),
+                )
+                    .in_set(OnUpdate(AppState::Playing)),
+            );
     }
 }
 
+fn regenerate_mana(mut query: Query<&mut Mana>, time: Res<Time>) {
+    for mut mana in query.iter_mut() {
+        mana.current = (mana.current + mana.regen * time.delta_seconds()).min(mana.total);
+    }
+}
+
+/// Physics body, sprite and animation of a projectile described by
+/// `definition`, fired from `origin` towards `direction` at `speed`. `scale`
+/// grows both the sprite and the collider.
+pub fn particle_bundle(
+    definition: &SpellDefinition,
+    origin: Vec3,
+    direction: Vec2,
+    speed: f32,
+    scale: f32,
+) -> impl Bundle {
+    (
+        RigidBody::KinematicVelocityBased,
+        Velocity {
+            linvel: direction * speed,
+            ..default()
+        },
+        definition.collider.to_collider(),
+        GravityScale(0.),
+        SpriteSheetBundle {
+            texture_atlas: definition.texture_atlas.clone(),
+            sprite: TextureAtlasSprite {
+                index: definition.animation.start,
+                custom_size: Some(Vec2::splat(definition.size)),
+                ..default()
+            },
+            transform: Transform::from_xyz(origin.x, origin.y, 0.)
+                .with_rotation(Quat::from_rotation_arc(Vec3::X, direction.extend(0.)))
+                .with_scale(Vec3::splat(scale)),
+            ..default()
+        },
+        Name::from(definition.name.clone()),
+        Animation {
+            start: definition.animation.start,
+            end: definition.animation.end,
+            timer: Timer::from_seconds(definition.animation.frame_time, TimerMode::Repeating),
+        },
+        Lifetime(Timer::from_seconds(definition.lifetime, TimerMode::Once)),
+    )
+}
+
 fn shoot_particle(
     mut commands: Commands,
-    sprite_sheet_maps: Res<SpriteSheetsMaps>,
+    spell_definitions: Res<Assets<SpellDefinition>>,
     mut spell_events: EventReader<SpellEvent>,
-    mut player_query: Query<&Transform, With<Player>>,
+    player_query: Query<(&Transform, &Stats), With<Player>>,
 ) {
-    let transform = player_query.single_mut();
+    let (transform, stats) = player_query.single();
     for spell_event in spell_events.iter() {
-        commands.spawn((
-            RigidBody::KinematicVelocityBased,
-            Velocity {
-                linvel: Vec2::new(spell_event.direction.x, spell_event.direction.y) * 300.,
-                ..default()
-            },
-            Collider::compound(vec![(Vec2::new(4.0, 0.0), 0., Collider::ball(10.))]),
-            GravityScale(0.),
-            SpriteSheetBundle {
-                texture_atlas: sprite_sheet_maps.fireball_atlas.clone(),
-                sprite: TextureAtlasSprite {
-                    index: 0,
-                    custom_size: Some(Vec2::splat(32.)),
-                    ..default()
-                },
-                transform: Transform::from_xyz(
-                    transform.translation.x,
-                    transform.translation.y,
-                    0.,
-                )
-                .with_rotation(Quat::from_rotation_arc(
-                    Vec3::X,
-                    spell_event.direction.extend(0.),
-                )),
-                ..default()
-            },
-            Name::from("Particle"),
-            Spell { damage: 8 },
-            Animation {
-                start: 0,
-                end: 2,
-                timer: Timer::from_seconds(0.1, TimerMode::Repeating),
-            },
-        ));
+        let Some(definition) = spell_definitions.get(&spell_event.spell) else {
+            continue;
+        };
+        let count = stats.get(Stat::ProjectileCount).round().max(1.) as usize;
+        let damage = (definition.damage as f32 * stats.get(Stat::Damage)).round() as usize;
+        let speed = definition.speed * stats.get(Stat::ProjectileSpeed);
+        for index in 0..count {
+            let angle = (index as f32 - (count - 1) as f32 / 2.) * SPREAD_ANGLE;
+            let direction = Vec2::from_angle(angle).rotate(spell_event.direction);
+            commands.spawn((
+                particle_bundle(definition, transform.translation, direction, speed, 1.),
+                CollisionGroups::new(PLAYER_SPELL_GROUP, ENEMY_GROUP | WALL_GROUP),
+                Spell::new(damage, definition.damage_type)
+                    .with_crit_chance(stats.get(Stat::CritChance)),
+            ));
+        }
     }
 }
 
-fn handle_particle_contacts(
+fn expire_particles(
     mut commands: Commands,
-    mut collision_events: EventReader<CollisionEvent>,
-    query: Query<(Entity, &mut Spell)>,
-    mut enemy_query: Query<(Entity, &mut Enemy, &mut Health, &mut TextureAtlasSprite)>,
-    mut score: ResMut<Score>,
+    mut query: Query<(Entity, &mut Lifetime)>,
+    time: Res<Time>,
 ) {
-    for collision_event in collision_events.iter() {
-        for (entity, particle) in query.iter() {
-            if let CollisionEvent::Started(e1, e2, _) = collision_event {
-                if e1 == &entity || e2 == &entity {
-                    commands.entity(entity).despawn();
-                    if let Some((entity, mut enemy, mut health, mut texture)) = enemy_query
-                        .iter_mut()
-                        .filter(|(enemy_entity, _, _, _)| enemy_entity == e1 || enemy_entity == e2)
-                        .next()
-                    {
-                        commands.entity(entity).insert(Damage::default());
-                        if health.current < particle.damage {
-                            health.current = 0;
-                        } else {
-                            health.current -= particle.damage;
-                        }
-                        texture.color = Color::rgba(255., 255., 255., 1.);
-                    };
-                }
-            }
+    for (entity, mut lifetime) in query.iter_mut() {
+        lifetime.0.tick(time.delta());
+        if lifetime.0.finished() {
+            commands.entity(entity).despawn();
         }
     }
+}
 
-    for (entity, enemy, health, _) in enemy_query.iter() {
-        if health.current == 0 {
-            commands.entity(entity).despawn();
-            score.value += 1;
+pub fn handle_particle_contacts(
+    mut commands: Commands,
+    mut collision_events: EventReader<CollisionEvent>,
+    mut damage_events: EventWriter<DamageEvent>,
+    mut query: Query<&mut Spell>,
+    enemy_query: Query<(), With<Enemy>>,
+    mut rng: ResMut<GameRng>,
+    mut spent: Local<HashSet<Entity>>,
+) {
+    // a particle can touch several enemies in the frame it runs out of pierce
+    spent.clear();
+    for collision_event in collision_events.iter() {
+        let CollisionEvent::Started(e1, e2, _) = collision_event else {
+            continue;
+        };
+        for (entity, enemy_entity) in [(*e1, *e2), (*e2, *e1)] {
+            // walls are handled by the dungeon
+            if !enemy_query.contains(enemy_entity) || spent.contains(&entity) {
+                continue;
+            }
+            let Ok(mut particle) = query.get_mut(entity) else {
+                continue;
+            };
+            if particle.pierce == 0 {
+                commands.entity(entity).despawn();
+                spent.insert(entity);
+            } else {
+                particle.pierce -= 1;
+            }
+            let crit = rng.gen::<f32>() < particle.crit_chance;
+            damage_events.send(DamageEvent {
+                source: entity,
+                target: enemy_entity,
+                amount: if crit {
+                    particle.damage * CRIT_MULTIPLIER
+                } else {
+                    particle.damage
+                },
+                damage_type: particle.damage_type,
+                crit,
+            });
         }
     }
 }
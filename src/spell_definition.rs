@@ -0,0 +1,113 @@
+use bevy::{
+    asset::{AssetLoader, AssetPath, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+use bevy_rapier2d::prelude::Collider;
+use serde::Deserialize;
+
+use crate::combat::DamageType;
+
+/// A spell as described by a `*.spell.ron` file under `assets/spells/`.
+#[derive(Deserialize, TypeUuid)]
+#[uuid = "5d3c1e0a-8f7b-4a51-9a0e-3f6d2b9c4e71"]
+pub struct SpellDefinition {
+    pub name: String,
+    pub speed: f32,
+    pub damage: usize,
+    /// Fire unless the file says otherwise.
+    #[serde(default)]
+    pub damage_type: DamageType,
+    pub mana_cost: f32,
+    /// Seconds before the spell can be cast again.
+    pub cooldown: f32,
+    pub collider: SpellCollider,
+    pub atlas: SpellAtlas,
+    pub size: f32,
+    pub animation: SpellAnimation,
+    /// Seconds before the projectile despawns on its own.
+    pub lifetime: f32,
+    #[serde(skip)]
+    pub texture_atlas: Handle<TextureAtlas>,
+}
+
+#[derive(Deserialize)]
+pub enum SpellCollider {
+    Ball {
+        radius: f32,
+        offset: (f32, f32),
+    },
+    Cuboid {
+        half_extents: (f32, f32),
+        offset: (f32, f32),
+    },
+}
+
+impl SpellCollider {
+    pub fn to_collider(&self) -> Collider {
+        match self {
+            SpellCollider::Ball { radius, offset } => {
+                Collider::compound(vec![((*offset).into(), 0., Collider::ball(*radius))])
+            }
+            SpellCollider::Cuboid {
+                half_extents,
+                offset,
+            } => Collider::compound(vec![(
+                (*offset).into(),
+                0.,
+                Collider::cuboid(half_extents.0, half_extents.1),
+            )]),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SpellAtlas {
+    pub texture: String,
+    pub tile_size: (f32, f32),
+    pub columns: usize,
+    pub rows: usize,
+    #[serde(default)]
+    pub padding: Option<(f32, f32)>,
+}
+
+#[derive(Deserialize)]
+pub struct SpellAnimation {
+    pub start: usize,
+    pub end: usize,
+    pub frame_time: f32,
+}
+
+#[derive(Default)]
+pub struct SpellDefinitionLoader;
+
+impl AssetLoader for SpellDefinitionLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let mut definition: SpellDefinition = ron::de::from_bytes(bytes)?;
+            let texture_path = AssetPath::new(definition.atlas.texture.clone().into(), None);
+            let texture_atlas = TextureAtlas::from_grid(
+                load_context.get_handle(texture_path.clone()),
+                definition.atlas.tile_size.into(),
+                definition.atlas.columns,
+                definition.atlas.rows,
+                definition.atlas.padding.map(Vec2::from),
+                None,
+            );
+            definition.texture_atlas =
+                load_context.set_labeled_asset("atlas", LoadedAsset::new(texture_atlas));
+            load_context
+                .set_default_asset(LoadedAsset::new(definition).with_dependency(texture_path));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["spell.ron"]
+    }
+}
@@ -1,5 +1,13 @@
 use bevy::prelude::*;
 
+const TILEMAP_COLUMNS: usize = 12;
+const TILEMAP_ROWS: usize = 11;
+
+/// Index of the tile at the 1-based `row` and `column` of `tilemap.png`.
+pub fn tile_index(row: usize, column: usize) -> usize {
+    (row - 1) * TILEMAP_COLUMNS + (column - 1)
+}
+
 pub struct SpriteSheetPlugin;
 impl Plugin for SpriteSheetPlugin {
     fn build(&self, app: &mut App) {
@@ -11,7 +19,6 @@ impl Plugin for SpriteSheetPlugin {
 #[derive(Resource)]
 pub struct SpriteSheetsMaps {
     pub characters_atlas: Handle<TextureAtlas>,
-    pub fireball_atlas: Handle<TextureAtlas>,
 }
 
 #[derive(Component)]
@@ -44,20 +51,11 @@ fn setup_sprite_sheets(
     let texture_atlas = TextureAtlas::from_grid(
         texture_handle,
         Vec2::new(16., 16.),
-        12,
-        11,
+        TILEMAP_COLUMNS,
+        TILEMAP_ROWS,
         Some(Vec2::new(1., 1.)),
         None,
     );
     let characters_atlas = texture_atlases.add(texture_atlas);
-    let player_index = (8 - 1) * 12 + (1 - 1);
-
-    let texture_handle = asset_server.load("sprites/fireball.png");
-    let texture_atlas =
-        TextureAtlas::from_grid(texture_handle, Vec2::new(16., 16.), 3, 1, None, None);
-    let fireball_atlas = texture_atlases.add(texture_atlas);
-    commands.insert_resource(SpriteSheetsMaps {
-        characters_atlas,
-        fireball_atlas,
-    });
+    commands.insert_resource(SpriteSheetsMaps { characters_atlas });
 }
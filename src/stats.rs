@@ -0,0 +1,200 @@
+use bevy::{
+    prelude::*,
+    utils::{Duration, HashMap},
+};
+
+use crate::{combat::Health, game_state::AppState};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Stat {
+    MaxHealth,
+    MoveSpeed,
+    /// Multiplier on the damage of every spell.
+    Damage,
+    /// Multiplier on the speed of every projectile.
+    ProjectileSpeed,
+    ProjectileCount,
+    /// Multiplier on spell cooldowns.
+    Cooldown,
+    /// Chance from 0 to 1 for a spell hit to deal double damage.
+    CritChance,
+}
+
+/// Where a modifier came from, so everything one source added can be taken
+/// off again, e.g. when an item is dropped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ModifierSource {
+    Upgrade,
+    // only upgrades hand out modifiers so far
+    #[allow(dead_code)]
+    Item,
+    #[allow(dead_code)]
+    Buff,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ModifierKind {
+    Additive(f32),
+    Multiplicative(f32),
+}
+
+pub struct StatModifier {
+    pub stat: Stat,
+    pub kind: ModifierKind,
+    pub source: ModifierSource,
+    /// Buffs wear off when this runs out. Modifiers without one are permanent.
+    pub duration: Option<Timer>,
+}
+
+impl StatModifier {
+    pub fn new(stat: Stat, kind: ModifierKind, source: ModifierSource) -> Self {
+        StatModifier {
+            stat,
+            kind,
+            source,
+            duration: None,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn with_duration(mut self, seconds: f32) -> Self {
+        self.duration = Some(Timer::from_seconds(seconds, TimerMode::Once));
+        self
+    }
+}
+
+/// Base values plus the modifiers stacked on top of them. The final value of a
+/// stat is `(base + additive) * multiplicative`.
+#[derive(Component)]
+pub struct Stats {
+    base: HashMap<Stat, f32>,
+    modifiers: Vec<StatModifier>,
+}
+
+impl Stats {
+    pub fn new(base: impl IntoIterator<Item = (Stat, f32)>) -> Self {
+        Stats {
+            base: base.into_iter().collect(),
+            modifiers: Vec::new(),
+        }
+    }
+
+    pub fn get(&self, stat: Stat) -> f32 {
+        let base = self.base.get(&stat).copied().unwrap_or_default();
+        let (additive, multiplicative) = self
+            .modifiers
+            .iter()
+            .filter(|modifier| modifier.stat == stat)
+            .fold(
+                (0., 1.),
+                |(additive, multiplicative), modifier| match modifier.kind {
+                    ModifierKind::Additive(value) => (additive + value, multiplicative),
+                    ModifierKind::Multiplicative(value) => (additive, multiplicative * value),
+                },
+            );
+        (base + additive) * multiplicative
+    }
+
+    /// `MaxHealth` as a whole number of hit points, never below 1.
+    pub fn max_health(&self) -> usize {
+        self.get(Stat::MaxHealth).round().max(1.) as usize
+    }
+
+    pub fn add_modifier(&mut self, modifier: StatModifier) {
+        self.modifiers.push(modifier);
+    }
+
+    #[allow(dead_code)]
+    pub fn remove_modifiers(&mut self, source: ModifierSource) {
+        self.modifiers.retain(|modifier| modifier.source != source);
+    }
+
+    /// Advances the buffs by `delta` and drops the ones that ran out.
+    fn tick_buffs(&mut self, delta: Duration) {
+        self.modifiers
+            .retain_mut(|modifier| match &mut modifier.duration {
+                Some(duration) => !duration.tick(delta).finished(),
+                None => true,
+            });
+    }
+}
+
+pub struct StatsPlugin;
+
+impl Plugin for StatsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            (expire_stat_modifiers, sync_max_health)
+                .chain()
+                .in_set(OnUpdate(AppState::Playing)),
+        );
+    }
+}
+
+fn expire_stat_modifiers(mut query: Query<&mut Stats>, time: Res<Time>) {
+    for mut stats in query.iter_mut() {
+        // only touch the ones with buffs, so `Changed<Stats>` stays quiet
+        if stats
+            .modifiers
+            .iter()
+            .any(|modifier| modifier.duration.is_some())
+        {
+            stats.tick_buffs(time.delta());
+        }
+    }
+}
+
+/// Keeps `Health::total` in line with the `MaxHealth` stat. Raising it heals by
+/// the same amount, lowering it only clamps the current health.
+fn sync_max_health(mut query: Query<(&Stats, &mut Health), Changed<Stats>>) {
+    for (stats, mut health) in query.iter_mut() {
+        let total = stats.max_health();
+        if total > health.total {
+            health.current += total - health.total;
+        }
+        health.total = total;
+        health.current = health.current.min(total);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn speed_modifier(kind: ModifierKind, source: ModifierSource) -> StatModifier {
+        StatModifier::new(Stat::MoveSpeed, kind, source)
+    }
+
+    #[test]
+    fn removing_a_source_keeps_the_others() {
+        let mut stats = Stats::new([(Stat::MoveSpeed, 100.)]);
+        stats.add_modifier(speed_modifier(
+            ModifierKind::Additive(20.),
+            ModifierSource::Upgrade,
+        ));
+        stats.add_modifier(speed_modifier(
+            ModifierKind::Multiplicative(1.5),
+            ModifierSource::Item,
+        ));
+        assert_eq!(stats.get(Stat::MoveSpeed), 180.);
+        stats.remove_modifiers(ModifierSource::Item);
+        assert_eq!(stats.get(Stat::MoveSpeed), 120.);
+    }
+
+    #[test]
+    fn buffs_wear_off() {
+        let mut stats = Stats::new([(Stat::MoveSpeed, 100.)]);
+        stats.add_modifier(speed_modifier(
+            ModifierKind::Additive(20.),
+            ModifierSource::Upgrade,
+        ));
+        stats.add_modifier(
+            speed_modifier(ModifierKind::Multiplicative(2.), ModifierSource::Buff)
+                .with_duration(5.),
+        );
+        stats.tick_buffs(Duration::from_secs(4));
+        assert_eq!(stats.get(Stat::MoveSpeed), 240.);
+        stats.tick_buffs(Duration::from_secs(1));
+        assert_eq!(stats.get(Stat::MoveSpeed), 120.);
+    }
+}
@@ -0,0 +1,287 @@
+use std::{f32::consts::TAU, sync::Arc};
+
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    audio::{Decodable, Source},
+    reflect::TypeUuid,
+    utils::{BoxedFuture, Duration},
+};
+use serde::Deserialize;
+
+const SAMPLE_RATE: u32 = 22_050;
+/// The mix is scaled so its loudest sample reaches this.
+const PEAK: f32 = 0.8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+enum Voice {
+    /// Soft, slowly swelling chord tone.
+    Pad,
+    /// Short plucked note.
+    Pluck,
+    Bass,
+    Kick,
+    Snare,
+    HiHat,
+}
+
+impl Voice {
+    /// Seconds the voice keeps sounding after its note ends.
+    fn release(&self) -> f32 {
+        match self {
+            Voice::Pad => 0.6,
+            Voice::Pluck => 0.5,
+            Voice::Bass => 0.05,
+            Voice::Kick => 0.3,
+            Voice::Snare => 0.2,
+            Voice::HiHat => 0.05,
+        }
+    }
+
+    /// The voice `time` seconds into a note of `length` seconds. `noise` is
+    /// a fresh random value from -1 to 1.
+    fn sample(&self, frequency: f32, time: f32, length: f32, noise: f32) -> f32 {
+        let phase = frequency * time;
+        let sine = |phase: f32| (phase * TAU).sin();
+        // fades out over the release once the note is over
+        let release = ((length + self.release() - time) / self.release()).clamp(0., 1.);
+        match self {
+            Voice::Pad => {
+                let attack = (time / 0.4).min(1.);
+                let tone = sine(phase) + sine(phase * 1.004) + 0.3 * sine(phase * 2.);
+                tone * 0.4 * attack * release
+            }
+            Voice::Pluck => {
+                let tone = sine(phase) + 0.25 * sine(phase * 2.) + 0.1 * sine(phase * 3.);
+                tone * (-time * 6.).exp() * release
+            }
+            Voice::Bass => {
+                // the first few odd harmonics of a square wave
+                let tone = sine(phase) + sine(phase * 3.) / 3. + sine(phase * 5.) / 5.;
+                let attack = (time / 0.01).min(1.);
+                tone * attack * (-time * 3.).exp() * release
+            }
+            Voice::Kick => {
+                let sweep = 45. * time + 75. * (1. - (-time * 30.).exp()) / 30.;
+                sine(sweep) * (-time * 12.).exp()
+            }
+            Voice::Snare => (0.7 * noise + 0.3 * sine(180. * time)) * (-time * 18.).exp(),
+            Voice::HiHat => noise * (-time * 60.).exp(),
+        }
+    }
+}
+
+struct Note {
+    voice: Voice,
+    /// In beats from the start of the loop.
+    start: f32,
+    /// In beats.
+    length: f32,
+    /// MIDI note number, 69 being the A at 440 Hz. Ignored by the drums.
+    pitch: u8,
+    volume: f32,
+}
+
+/// A song as described by a `*.song.ron` file under `assets/audio/music/`:
+/// a chord progression, and parts that repeat a pattern over every chord.
+#[derive(Deserialize)]
+struct SongDefinition {
+    /// In beats per minute.
+    tempo: f32,
+    beats_per_chord: f32,
+    chords: Vec<Chord>,
+    parts: Vec<Part>,
+}
+
+impl SongDefinition {
+    fn beats(&self) -> f32 {
+        self.chords.len() as f32 * self.beats_per_chord
+    }
+
+    fn notes(&self) -> Vec<Note> {
+        let mut notes = Vec::new();
+        for (index, chord) in self.chords.iter().enumerate() {
+            let chord_start = index as f32 * self.beats_per_chord;
+            for part in &self.parts {
+                let steps = (self.beats_per_chord / part.step) as usize;
+                for (step, pitches) in part.pattern.iter().cycle().take(steps).enumerate() {
+                    for pitch in pitches {
+                        notes.push(Note {
+                            voice: part.voice,
+                            start: chord_start + step as f32 * part.step,
+                            length: part.length,
+                            pitch: chord.pitch(*pitch).saturating_add_signed(part.transpose),
+                            volume: part.volume,
+                        });
+                    }
+                }
+            }
+        }
+        notes
+    }
+}
+
+#[derive(Deserialize)]
+struct Chord {
+    /// MIDI note number of the root.
+    root: u8,
+    minor: bool,
+}
+
+impl Chord {
+    fn pitch(&self, pitch: Pitch) -> u8 {
+        match pitch {
+            Pitch::Tone(index) => {
+                let third = if self.minor { 3 } else { 4 };
+                let octave = 12 * (index / 3) as u8;
+                self.root + octave + [0, third, 7][index % 3]
+            }
+            Pitch::Interval(semitones) => self.root + semitones,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Deserialize)]
+enum Pitch {
+    /// Root, third and fifth of the chord, then on up an octave at a time.
+    Tone(usize),
+    /// Semitones above the root of the chord.
+    Interval(u8),
+}
+
+#[derive(Deserialize)]
+struct Part {
+    voice: Voice,
+    volume: f32,
+    /// Semitones added to every note of the part.
+    #[serde(default)]
+    transpose: i8,
+    /// Beats from one step of `pattern` to the next.
+    step: f32,
+    /// Beats each note is held.
+    length: f32,
+    /// Notes struck on each step, looped for as long as a chord lasts. An
+    /// empty step is a rest, and the drums ignore which note they are given.
+    pattern: Vec<Vec<Pitch>>,
+}
+
+/// A looping piece of music rendered from a `*.song.ron` file. Plays through
+/// `Audio<Song>` like a sound file would through `Audio`.
+#[derive(TypeUuid)]
+#[uuid = "c41e8b27-5a9d-4f60-b3e2-7d18a6f0c953"]
+pub struct Song {
+    samples: Arc<[f32]>,
+}
+
+impl Song {
+    /// Renders `notes` into a loop of `beats` at `tempo` beats per minute.
+    /// Notes still ringing at the end carry over into the start, so the loop
+    /// has no seam.
+    fn render(tempo: f32, beats: f32, notes: &[Note]) -> Self {
+        let seconds_per_beat = 60. / tempo;
+        let len = (beats * seconds_per_beat * SAMPLE_RATE as f32) as usize;
+        let mut samples = vec![0.; len];
+        // xorshift, so the drums sound the same every time
+        let mut state = 0x9e37_79b9_u32;
+        let mut noise = || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as f32 / u32::MAX as f32 * 2. - 1.
+        };
+        for note in notes {
+            let frequency = 440. * 2_f32.powf((note.pitch as f32 - 69.) / 12.);
+            let length = note.length * seconds_per_beat;
+            let start = (note.start * seconds_per_beat * SAMPLE_RATE as f32) as usize;
+            let duration = ((length + note.voice.release()) * SAMPLE_RATE as f32) as usize;
+            for offset in 0..duration {
+                let time = offset as f32 / SAMPLE_RATE as f32;
+                samples[(start + offset) % len] +=
+                    note.volume * note.voice.sample(frequency, time, length, noise());
+            }
+        }
+        let loudest = samples
+            .iter()
+            .fold(0_f32, |loudest, sample| loudest.max(sample.abs()));
+        if loudest > 0. {
+            for sample in samples.iter_mut() {
+                *sample *= PEAK / loudest;
+            }
+        }
+        Song {
+            samples: samples.into(),
+        }
+    }
+}
+
+pub struct SongDecoder {
+    samples: Arc<[f32]>,
+    position: usize,
+}
+
+impl Iterator for SongDecoder {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.samples.get(self.position).copied();
+        self.position += 1;
+        sample
+    }
+}
+
+impl Source for SongDecoder {
+    fn current_frame_len(&self) -> Option<usize> {
+        Some(self.samples.len().saturating_sub(self.position))
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        Some(Duration::from_secs_f32(
+            self.samples.len() as f32 / SAMPLE_RATE as f32,
+        ))
+    }
+}
+
+impl Decodable for Song {
+    type DecoderItem = f32;
+    type Decoder = SongDecoder;
+
+    fn decoder(&self) -> SongDecoder {
+        SongDecoder {
+            samples: self.samples.clone(),
+            position: 0,
+        }
+    }
+}
+
+/// Renders songs while they load, so the synthesis stays off the main thread.
+#[derive(Default)]
+pub struct SongLoader;
+
+impl AssetLoader for SongLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let definition: SongDefinition = ron::de::from_bytes(bytes)?;
+            if definition.chords.is_empty() {
+                return Err(bevy::asset::Error::msg("song has no chords"));
+            }
+            let song = Song::render(definition.tempo, definition.beats(), &definition.notes());
+            load_context.set_default_asset(LoadedAsset::new(song));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["song.ron"]
+    }
+}
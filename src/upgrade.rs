@@ -0,0 +1,227 @@
+use bevy::prelude::*;
+use rand::seq::SliceRandom;
+
+use crate::{
+    controls::gamepad_just_pressed,
+    experience::Experience,
+    game_state::AppState,
+    player::Player,
+    rng::GameRng,
+    stats::{ModifierKind, ModifierSource, Stat, StatModifier, Stats},
+    weapon::{WeaponKind, Weapons},
+};
+
+const CHOICE_COUNT: usize = 3;
+/// Keys and gamepad buttons that pick the choices, in order.
+const CHOICE_KEYS: [KeyCode; CHOICE_COUNT] = [KeyCode::Key1, KeyCode::Key2, KeyCode::Key3];
+const CHOICE_BUTTONS: [GamepadButtonType; CHOICE_COUNT] = [
+    GamepadButtonType::DPadLeft,
+    GamepadButtonType::DPadUp,
+    GamepadButtonType::DPadRight,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Upgrade {
+    Damage,
+    ProjectileCount,
+    Cooldown,
+    MaxHealth,
+    MoveSpeed,
+    CritChance,
+    /// Unlocks the weapon, or raises its level if the player already has it.
+    Weapon(WeaponKind),
+}
+
+impl Upgrade {
+    const STATS: [Upgrade; 6] = [
+        Upgrade::Damage,
+        Upgrade::ProjectileCount,
+        Upgrade::Cooldown,
+        Upgrade::MaxHealth,
+        Upgrade::MoveSpeed,
+        Upgrade::CritChance,
+    ];
+
+    /// Every upgrade the player can still take.
+    fn available(weapons: &Weapons) -> Vec<Upgrade> {
+        let weapon_upgrades = WeaponKind::ALL
+            .into_iter()
+            .filter(|kind| {
+                weapons
+                    .get(*kind)
+                    .is_none_or(|weapon| !weapon.is_max_level())
+            })
+            .map(Upgrade::Weapon);
+        Upgrade::STATS.into_iter().chain(weapon_upgrades).collect()
+    }
+
+    fn description(&self, weapons: &Weapons) -> String {
+        match self {
+            Upgrade::Damage => "+25% spell damage".to_string(),
+            Upgrade::ProjectileCount => "+1 projectile".to_string(),
+            Upgrade::Cooldown => "-10% cooldowns".to_string(),
+            Upgrade::MaxHealth => "+2 max health".to_string(),
+            Upgrade::MoveSpeed => "+15 move speed".to_string(),
+            Upgrade::CritChance => "+5% critical hit chance".to_string(),
+            Upgrade::Weapon(kind) => match weapons.get(*kind) {
+                Some(weapon) => format!("{} level {}", kind.name(), weapon.level + 2),
+                None => format!("New weapon: {}", kind.name()),
+            },
+        }
+    }
+
+    fn apply(&self, stats: &mut Stats, weapons: &mut Weapons, asset_server: &AssetServer) {
+        let (stat, kind) = match self {
+            Upgrade::Damage => (Stat::Damage, ModifierKind::Additive(0.25)),
+            Upgrade::ProjectileCount => (Stat::ProjectileCount, ModifierKind::Additive(1.)),
+            Upgrade::Cooldown => (Stat::Cooldown, ModifierKind::Multiplicative(0.9)),
+            Upgrade::MaxHealth => (Stat::MaxHealth, ModifierKind::Additive(2.)),
+            Upgrade::MoveSpeed => (Stat::MoveSpeed, ModifierKind::Additive(15.)),
+            Upgrade::CritChance => (Stat::CritChance, ModifierKind::Additive(0.05)),
+            Upgrade::Weapon(kind) => {
+                weapons.upgrade(*kind, asset_server);
+                return;
+            }
+        };
+        stats.add_modifier(StatModifier::new(stat, kind, ModifierSource::Upgrade));
+    }
+}
+
+#[derive(Resource)]
+struct UpgradeChoices(Vec<Upgrade>);
+
+#[derive(Component)]
+struct LevelUpScreen;
+
+#[derive(Component)]
+struct UpgradeButton(usize);
+
+pub struct UpgradePlugin;
+
+impl Plugin for UpgradePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(spawn_level_up_screen.in_schedule(OnEnter(AppState::LevelUp)))
+            .add_system(despawn_level_up_screen.in_schedule(OnExit(AppState::LevelUp)))
+            .add_system(choose_upgrade.in_set(OnUpdate(AppState::LevelUp)));
+    }
+}
+
+fn spawn_level_up_screen(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    player_query: Query<&Weapons, With<Player>>,
+    mut rng: ResMut<GameRng>,
+) {
+    let weapons = player_query.single();
+    let choices: Vec<Upgrade> = Upgrade::available(weapons)
+        .choose_multiple(&mut **rng, CHOICE_COUNT)
+        .copied()
+        .collect();
+    let font = asset_server.load("fonts/DMSans-Regular.ttf");
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                    flex_direction: FlexDirection::Column,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    gap: Size::all(Val::Px(10.)),
+                    ..default()
+                },
+                background_color: Color::rgba(0., 0., 0., 0.6).into(),
+                ..default()
+            },
+            LevelUpScreen,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                "Level up!",
+                TextStyle {
+                    font: font.clone(),
+                    font_size: 60.,
+                    color: Color::WHITE,
+                },
+            ));
+            parent.spawn(TextBundle::from_section(
+                "Pick with 1, 2 and 3, or left, up and right on the d-pad",
+                TextStyle {
+                    font: font.clone(),
+                    font_size: 20.,
+                    color: Color::WHITE,
+                },
+            ));
+            for (index, upgrade) in choices.iter().enumerate() {
+                parent
+                    .spawn((
+                        ButtonBundle {
+                            style: Style {
+                                size: Size::new(Val::Px(320.), Val::Px(50.)),
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..default()
+                            },
+                            background_color: Color::rgb(0.2, 0.2, 0.3).into(),
+                            ..default()
+                        },
+                        UpgradeButton(index),
+                    ))
+                    .with_children(|button| {
+                        button.spawn(TextBundle::from_section(
+                            format!("{}. {}", index + 1, upgrade.description(weapons)),
+                            TextStyle {
+                                font: font.clone(),
+                                font_size: 26.,
+                                color: Color::WHITE,
+                            },
+                        ));
+                    });
+            }
+        });
+    commands.insert_resource(UpgradeChoices(choices));
+}
+
+fn despawn_level_up_screen(mut commands: Commands, query: Query<Entity, With<LevelUpScreen>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    commands.remove_resource::<UpgradeChoices>();
+}
+
+#[allow(clippy::too_many_arguments)]
+fn choose_upgrade(
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepad_input: Res<Input<GamepadButton>>,
+    gamepads: Res<Gamepads>,
+    button_query: Query<(&Interaction, &UpgradeButton), Changed<Interaction>>,
+    choices: Res<UpgradeChoices>,
+    mut player_query: Query<(&mut Stats, &mut Weapons, &mut Experience), With<Player>>,
+    mut next_state: ResMut<NextState<AppState>>,
+    asset_server: Res<AssetServer>,
+) {
+    let pressed = CHOICE_KEYS
+        .iter()
+        .zip(CHOICE_BUTTONS)
+        .take(choices.0.len())
+        .position(|(key, button)| {
+            keyboard_input.just_pressed(*key)
+                || gamepad_just_pressed(&gamepads, &gamepad_input, button)
+        });
+    let clicked = button_query
+        .iter()
+        .find(|(interaction, _)| **interaction == Interaction::Clicked)
+        .map(|(_, button)| button.0);
+    let Some(index) = pressed.or(clicked) else {
+        return;
+    };
+
+    let (mut stats, mut weapons, mut experience) = player_query.single_mut();
+    choices.0[index].apply(&mut stats, &mut weapons, &asset_server);
+    experience.pending_level_ups -= 1;
+    // Entering `LevelUp` again rolls a new set of choices.
+    next_state.set(if experience.pending_level_ups > 0 {
+        AppState::LevelUp
+    } else {
+        AppState::Playing
+    });
+}
@@ -1,96 +1,344 @@
-use std::cmp::max;
+use std::f32::consts::TAU;
 
-use bevy::{prelude::*, window::PrimaryWindow};
+use bevy::prelude::*;
 use bevy_rapier2d::prelude::*;
-use rand::random;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng};
 
-use crate::{combat::Health, enemy::Enemy};
+use crate::{
+    boss::{spawn_boss, BossKind},
+    dungeon::{CurrentDungeon, DungeonMap},
+    enemy::{spawn_enemy, EnemyKind, EnemyRegistry},
+    game_state::AppState,
+    player::Player,
+    rng::{GameRng, RngSet},
+    sprite_sheets::SpriteSheetsMaps,
+    wave_schedule::{SpawnPattern, WaveEvent, WaveSchedule, WaveScheduleLoader},
+};
 
-const ENEMY_SIZE: f32 = 32.;
+/// How far outside the camera view enemies appear.
+const SPAWN_MARGIN: f32 = 40.;
+/// Width of the ring spawn points are picked from.
+const SPAWN_RING_WIDTH: f32 = 120.;
+/// Enemies never spawn closer than this to the player, even with a tiny view.
+const MIN_SPAWN_DISTANCE: f32 = 300.;
+const SPAWN_ATTEMPTS: usize = 16;
+/// Distance between neighbours in a `Line`.
+const LINE_SPACING: f32 = 36.;
+/// Radius of the area a `Cluster` is spread over.
+const CLUSTER_RADIUS: f32 = 60.;
+/// How many of the level's spawn points closest to the player are used.
+const MAX_SPAWN_POINTS: usize = 12;
+/// Enemies sharing a spawn point are scattered up to this far from it.
+const SPAWN_JITTER: f32 = 24.;
 
 #[derive(Component)]
 pub struct Wave {
-    index: u32,
+    pub index: u32,
+    /// Counts down to the next wave. Set from the next entry's `delay`.
     timer: Timer,
+    schedule: Handle<WaveSchedule>,
+    /// Bosses that found no clear spot to spawn at yet. They are tried again
+    /// every frame, so a boss fight is never skipped.
+    pending_bosses: Vec<BossKind>,
+}
+
+impl Wave {
+    /// Seconds until the next wave spawns.
+    pub fn time_to_next(&self) -> f32 {
+        self.timer.remaining_secs()
+    }
 }
 
 pub struct WavePlugin;
 
 impl Plugin for WavePlugin {
     fn build(&self, app: &mut App) {
-        app.add_startup_system(spawn_wave)
-            .add_system(spawn_enemy_wave);
+        app.add_asset::<WaveSchedule>()
+            .init_asset_loader::<WaveScheduleLoader>()
+            .add_system(
+                spawn_wave
+                    .run_if(not(any_with_component::<Wave>()))
+                    .in_schedule(OnEnter(AppState::Playing)),
+            )
+            .add_system(
+                spawn_enemy_wave
+                    .in_set(RngSet::Spawns)
+                    .in_set(OnUpdate(AppState::Playing)),
+            );
     }
 }
 
-fn spawn_wave(mut commands: Commands) {
+fn spawn_wave(mut commands: Commands, asset_server: Res<AssetServer>) {
     commands.spawn(Wave {
         index: 0,
-        timer: Timer::from_seconds(10., TimerMode::Repeating),
+        // the real delay is read from the schedule once it has loaded
+        timer: Timer::default(),
+        schedule: asset_server.load("waves/default.waves.ron"),
+        pending_bosses: Vec::new(),
     });
 }
 
+/// A ring around the player that lies just outside the camera view.
+struct SpawnRing {
+    center: Vec2,
+    inner_radius: f32,
+    /// The level's enemy spawn points that are out of view. When there are
+    /// any, enemies appear around them instead of anywhere on the ring.
+    spawn_points: Vec<Vec2>,
+}
+
+impl SpawnRing {
+    fn new(center: Vec2, view: Rect, level_spawn_points: impl IntoIterator<Item = Vec2>) -> Self {
+        // half the view's diagonal is the farthest visible point from its center
+        let inner_radius = (view.half_size().length() + SPAWN_MARGIN).max(MIN_SPAWN_DISTANCE);
+        let mut spawn_points: Vec<Vec2> = level_spawn_points
+            .into_iter()
+            .filter(|point| point.distance(center) >= inner_radius)
+            .collect();
+        // the closest ones, so enemies don't start on the far side of the level
+        spawn_points.sort_by(|a, b| {
+            a.distance_squared(center)
+                .total_cmp(&b.distance_squared(center))
+        });
+        spawn_points.truncate(MAX_SPAWN_POINTS);
+        SpawnRing {
+            center,
+            inner_radius,
+            spawn_points,
+        }
+    }
+
+    /// Picks a random point where an enemy of `radius` fits.
+    fn spawn_point(&self, rng: &mut impl Rng, area: &SpawnArea, radius: f32) -> Option<Vec2> {
+        (0..SPAWN_ATTEMPTS)
+            .map(|_| {
+                let anchor = self.anchor(rng);
+                if self.spawn_points.is_empty() {
+                    anchor
+                } else {
+                    jitter(anchor, rng)
+                }
+            })
+            .find(|point| area.is_clear(*point, radius))
+    }
+
+    /// A random spawn point of the level, or a random point on the ring if the
+    /// level has none out of view.
+    fn anchor(&self, rng: &mut impl Rng) -> Vec2 {
+        if let Some(point) = self.spawn_points.choose(rng) {
+            return *point;
+        }
+        let angle = rng.gen_range(0. ..TAU);
+        let radius = self.inner_radius + rng.gen_range(0. ..SPAWN_RING_WIDTH);
+        self.center + Vec2::from_angle(angle) * radius
+    }
+
+    /// Lays out `count` spawn points in `pattern`. Points where an enemy of
+    /// `radius` doesn't fit are dropped, so fewer than `count` may come back.
+    fn pattern_points(
+        &self,
+        pattern: SpawnPattern,
+        count: usize,
+        rng: &mut impl Rng,
+        area: &SpawnArea,
+        radius: f32,
+    ) -> Vec<Vec2> {
+        let points: Vec<Vec2> = match pattern {
+            SpawnPattern::Ring => {
+                return (0..count)
+                    .filter_map(|_| self.spawn_point(rng, area, radius))
+                    .collect();
+            }
+            SpawnPattern::Line => {
+                let start = self.anchor(rng);
+                let direction = (start - self.center).normalize_or_zero();
+                let offset = (count as f32 - 1.) / 2.;
+                (0..count)
+                    .map(|index| start + direction.perp() * (index as f32 - offset) * LINE_SPACING)
+                    .collect()
+            }
+            SpawnPattern::Cluster => {
+                let center = self.anchor(rng);
+                (0..count)
+                    .map(|_| {
+                        let angle = rng.gen_range(0. ..TAU);
+                        center + Vec2::from_angle(angle) * rng.gen_range(0. ..CLUSTER_RADIUS)
+                    })
+                    .collect()
+            }
+            SpawnPattern::Surround => {
+                let start = rng.gen_range(0. ..TAU);
+                (0..count)
+                    .map(|index| {
+                        let direction = Vec2::from_angle(start + index as f32 / count as f32 * TAU);
+                        // the spawn point that lies closest to the wanted direction
+                        let closest = self.spawn_points.iter().min_by(|a, b| {
+                            let angle_to =
+                                |point: &Vec2| direction.angle_between(*point - self.center).abs();
+                            angle_to(a).total_cmp(&angle_to(b))
+                        });
+                        match closest {
+                            Some(point) => jitter(*point, rng),
+                            None => self.center + direction * self.inner_radius,
+                        }
+                    })
+                    .collect()
+            }
+        };
+        points
+            .into_iter()
+            .filter(|point| area.is_clear(*point, radius))
+            .collect()
+    }
+}
+
+fn jitter(point: Vec2, rng: &mut impl Rng) -> Vec2 {
+    point + Vec2::from_angle(rng.gen_range(0. ..TAU)) * rng.gen_range(0. ..SPAWN_JITTER)
+}
+
+/// Where enemies can be placed: on the level's floor, clear of walls and
+/// other static colliders.
+struct SpawnArea<'a> {
+    map: Option<&'a DungeonMap>,
+    rapier_context: &'a RapierContext,
+}
+
+impl SpawnArea<'_> {
+    /// Whether an enemy of `radius` fits at `point`. Off the map's floor it
+    /// would be stuck out in the void, where it can never reach the player.
+    fn is_clear(&self, point: Vec2, radius: f32) -> bool {
+        if let Some(map) = self.map {
+            if !map.get(DungeonMap::cell_at(point)).is_walkable() {
+                return false;
+            }
+        }
+        self.rapier_context
+            .intersection_with_shape(
+                point,
+                0.,
+                &Collider::ball(radius),
+                QueryFilter::only_fixed(),
+            )
+            .is_none()
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn spawn_enemy_wave(
     mut commands: Commands,
-    window_query: Query<&Window, With<PrimaryWindow>>,
-    asset_server: Res<AssetServer>,
-    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+    player_query: Query<&Transform, With<Player>>,
+    camera_query: Query<&OrthographicProjection, With<Camera2d>>,
+    rapier_context: Res<RapierContext>,
+    sprite_sheets_maps: Res<SpriteSheetsMaps>,
+    enemy_registry: Res<EnemyRegistry>,
+    wave_schedules: Res<Assets<WaveSchedule>>,
+    current_dungeon: Option<Res<CurrentDungeon>>,
+    dungeon_maps: Res<Assets<DungeonMap>>,
+    mut rng: ResMut<GameRng>,
     time: Res<Time>,
     mut query: Query<&mut Wave>,
 ) {
     let mut wave = query.get_single_mut().unwrap();
+    let Some(schedule) = wave_schedules.get(&wave.schedule) else {
+        return;
+    };
+    if wave.index == 0 && wave.timer.duration().is_zero() {
+        let (first, _) = schedule.entry(1);
+        wave.timer = Timer::from_seconds(first.delay, TimerMode::Once);
+    }
     wave.timer.tick(time.delta());
-    if wave.timer.just_finished() {
-        wave.index += 1;
-        wave.timer.reset();
-
-        let window = window_query.get_single().unwrap();
-        let texture_handle = asset_server.load("sprites/tilemap.png");
-        let texture_atlas = TextureAtlas::from_grid(
-            texture_handle,
-            Vec2::new(16., 16.),
-            12,
-            11,
-            Some(Vec2::new(1., 1.)),
-            None,
+    let wave_started = wave.timer.just_finished();
+    if !wave_started && wave.pending_bosses.is_empty() {
+        return;
+    }
+
+    let player_translation = player_query.single().translation;
+    let map = current_dungeon.and_then(|current_dungeon| dungeon_maps.get(&current_dungeon.0));
+    let area = SpawnArea {
+        map,
+        rapier_context: &rapier_context,
+    };
+    let level_spawn_points = map
+        .map(|map| map.enemy_spawns.as_slice())
+        .unwrap_or_default()
+        .iter()
+        .map(|cell| DungeonMap::cell_center(cell.as_ivec2()));
+    let ring = SpawnRing::new(
+        player_translation.truncate(),
+        camera_query.single().area,
+        level_spawn_points,
+    );
+    let rng = &mut **rng;
+    let radius = |kind: EnemyKind| enemy_registry.get(kind).size / 2.;
+    let spawn = |commands: &mut Commands, kind: EnemyKind, point: Vec2| {
+        spawn_enemy(
+            commands,
+            &enemy_registry,
+            &sprite_sheets_maps,
+            kind,
+            point.extend(0.),
+        )
+    };
+
+    let try_spawn_boss = |commands: &mut Commands, rng: &mut StdRng, kind: BossKind| {
+        let Some(point) = ring.spawn_point(rng, &area, radius(kind.enemy_kind())) else {
+            return false;
+        };
+        spawn_boss(
+            commands,
+            &enemy_registry,
+            &sprite_sheets_maps,
+            kind,
+            point.extend(0.),
         );
-        let texture_atlas_handle = texture_atlases.add(texture_atlas);
-        let enemy_index = (11 - 1) * 12 + (2 - 1);
-
-        let enemy_count = ((wave.index as f32).log(1.1) + 10.) as usize;
-        println!("{}", enemy_count);
-        for _ in 0..enemy_count {
-            let radious = max(window.height() as usize, window.width() as usize) as f32 / 2.;
-            let angle = (random::<f32>() * 360.0).to_radians();
-            let x = window.width() / 2. + radious * angle.cos();
-            let y = window.height() / 2. + radious * angle.sin();
-            commands.spawn((
-                RigidBody::KinematicVelocityBased,
-                Collider::cuboid(ENEMY_SIZE / 2., ENEMY_SIZE / 2.),
-                LockedAxes::ROTATION_LOCKED,
-                Velocity {
-                    ..Default::default()
-                },
-                GravityScale(0.),
-                ActiveEvents::COLLISION_EVENTS,
-                CollisionGroups::new(Group::GROUP_1, Group::GROUP_2),
-                (ActiveCollisionTypes::default() | ActiveCollisionTypes::KINEMATIC_KINEMATIC),
-                SpriteSheetBundle {
-                    texture_atlas: texture_atlas_handle.clone(),
-                    sprite: TextureAtlasSprite {
-                        index: enemy_index,
-                        custom_size: Some(Vec2::splat(ENEMY_SIZE)),
-                        ..default()
-                    },
-                    transform: Transform::from_xyz(x, y, 0.),
-                    ..default()
-                },
-                Enemy,
-                Health {
-                    total: 16,
-                    current: 16,
-                },
-            ));
+        true
+    };
+
+    let mut pending_bosses = std::mem::take(&mut wave.pending_bosses);
+    pending_bosses.retain(|kind| !try_spawn_boss(&mut commands, rng, *kind));
+    if !wave_started {
+        wave.pending_bosses = pending_bosses;
+        return;
+    }
+
+    wave.index += 1;
+    let (entry, multiplier) = schedule.entry(wave.index);
+    let (next, _) = schedule.entry(wave.index + 1);
+    wave.timer = Timer::from_seconds(next.delay, TimerMode::Once);
+
+    for group in &entry.spawns {
+        let count = (group.count as f32 * multiplier).round() as usize;
+        for point in ring.pattern_points(group.pattern, count, rng, &area, radius(group.enemy)) {
+            spawn(&mut commands, group.enemy, point);
+        }
+    }
+    for event in &entry.events {
+        match event {
+            WaveEvent::Boss(kind) => {
+                if !try_spawn_boss(&mut commands, rng, *kind) {
+                    warn!("no clear spot for the {kind:?} boss, trying again next frame");
+                    pending_bosses.push(*kind);
+                }
+            }
+            WaveEvent::Swarm {
+                enemy,
+                count,
+                groups,
+            } => {
+                let per_group = count / (*groups).max(1);
+                for _ in 0..*groups {
+                    for point in ring.pattern_points(
+                        SpawnPattern::Cluster,
+                        per_group,
+                        rng,
+                        &area,
+                        radius(*enemy),
+                    ) {
+                        spawn(&mut commands, *enemy, point);
+                    }
+                }
+            }
         }
     }
+    wave.pending_bosses = pending_bosses;
 }
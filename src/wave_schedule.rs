@@ -0,0 +1,130 @@
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+use serde::Deserialize;
+
+use crate::{boss::BossKind, enemy::EnemyKind};
+
+/// The wave timeline as described by a `*.waves.ron` file under `assets/waves/`.
+#[derive(Deserialize, TypeUuid)]
+#[uuid = "b7e2a4c9-1d6f-4e83-8a5b-6c0f9d2e7a14"]
+pub struct WaveSchedule {
+    pub waves: Vec<WaveEntry>,
+    /// Waves past the end of `waves` repeat the last entry, with every count
+    /// multiplied by this once per extra wave.
+    pub overflow_growth: f32,
+}
+
+impl WaveSchedule {
+    fn from_bytes(bytes: &[u8]) -> Result<Self, bevy::asset::Error> {
+        let schedule: WaveSchedule = ron::de::from_bytes(bytes)?;
+        if schedule.waves.is_empty() {
+            return Err(bevy::asset::Error::msg("wave schedule has no waves"));
+        }
+        Ok(schedule)
+    }
+
+    /// The entry for a 1-based wave index, and the multiplier for its counts.
+    pub fn entry(&self, index: u32) -> (&WaveEntry, f32) {
+        let position = index.saturating_sub(1) as usize;
+        match self.waves.get(position) {
+            Some(entry) => (entry, 1.),
+            None => {
+                let extra = position + 1 - self.waves.len();
+                (
+                    self.waves
+                        .last()
+                        .expect("the loader rejects schedules without waves"),
+                    self.overflow_growth.powi(extra as i32),
+                )
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct WaveEntry {
+    /// Seconds between the previous wave and this one.
+    pub delay: f32,
+    #[serde(default)]
+    pub spawns: Vec<SpawnGroup>,
+    #[serde(default)]
+    pub events: Vec<WaveEvent>,
+}
+
+#[derive(Deserialize)]
+pub struct SpawnGroup {
+    pub enemy: EnemyKind,
+    pub count: usize,
+    pub pattern: SpawnPattern,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+pub enum SpawnPattern {
+    /// Scattered at random around the spawn ring.
+    Ring,
+    /// A row of enemies on one side of the player.
+    Line,
+    /// Bunched together at one random point of the ring.
+    Cluster,
+    /// Evenly spaced all the way around the player.
+    Surround,
+}
+
+#[derive(Deserialize)]
+pub enum WaveEvent {
+    Boss(BossKind),
+    /// A large horde arriving in `groups` clusters at once.
+    Swarm {
+        enemy: EnemyKind,
+        count: usize,
+        groups: usize,
+    },
+}
+
+#[derive(Default)]
+pub struct WaveScheduleLoader;
+
+impl AssetLoader for WaveScheduleLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let schedule = WaveSchedule::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(schedule));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["waves.ron"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn schedules_without_waves_are_rejected() {
+        let error = WaveSchedule::from_bytes(b"(waves: [], overflow_growth: 1.1)")
+            .err()
+            .unwrap();
+        assert_eq!(error.to_string(), "wave schedule has no waves");
+    }
+
+    #[test]
+    fn the_default_schedule_loads() {
+        let bytes = include_bytes!("../assets/waves/default.waves.ron");
+        let schedule = WaveSchedule::from_bytes(bytes).unwrap();
+        // past the end the last wave repeats, growing each time
+        let (last, _) = schedule.entry(schedule.waves.len() as u32);
+        let (overflow, multiplier) = schedule.entry(schedule.waves.len() as u32 + 2);
+        assert!(std::ptr::eq(last, overflow));
+        assert_eq!(multiplier, schedule.overflow_growth.powi(2));
+    }
+}
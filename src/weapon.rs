@@ -0,0 +1,269 @@
+use std::f32::consts::TAU;
+
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use rand::Rng;
+
+use crate::{
+    combat::{ENEMY_GROUP, PLAYER_SPELL_GROUP, WALL_GROUP},
+    enemy::EnemySpatialHash,
+    game_state::AppState,
+    player::Player,
+    rng::{GameRng, RngSet},
+    spell::{particle_bundle, Spell, SPREAD_ANGLE},
+    spell_definition::SpellDefinition,
+    stats::{Stat, Stats},
+};
+
+const ORBIT_RADIUS: f32 = 70.;
+/// Enemies further away than this are not targeted, they are well off screen.
+pub const TARGET_RANGE: f32 = 2048.;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WeaponKind {
+    MagicMissile,
+    Ember,
+    OrbitingFlame,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Targeting {
+    NearestEnemy,
+    RandomDirection,
+    /// Projectiles circle the player. The spell's `speed` is read as radians
+    /// per second.
+    Orbit,
+}
+
+pub struct WeaponLevel {
+    pub projectiles: usize,
+    /// Enemies a projectile passes through before it is destroyed.
+    pub pierce: u32,
+    /// Scale applied to the projectile sprite and collider.
+    pub area: f32,
+}
+
+const fn level(projectiles: usize, pierce: u32, area: f32) -> WeaponLevel {
+    WeaponLevel {
+        projectiles,
+        pierce,
+        area,
+    }
+}
+
+impl WeaponKind {
+    pub const ALL: [WeaponKind; 3] = [
+        WeaponKind::MagicMissile,
+        WeaponKind::Ember,
+        WeaponKind::OrbitingFlame,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            WeaponKind::MagicMissile => "Magic Missile",
+            WeaponKind::Ember => "Ember",
+            WeaponKind::OrbitingFlame => "Orbiting Flame",
+        }
+    }
+
+    fn spell_path(&self) -> &'static str {
+        match self {
+            WeaponKind::MagicMissile => "spells/magic_missile.spell.ron",
+            WeaponKind::Ember => "spells/ember.spell.ron",
+            WeaponKind::OrbitingFlame => "spells/orbiting_flame.spell.ron",
+        }
+    }
+
+    fn targeting(&self) -> Targeting {
+        match self {
+            WeaponKind::MagicMissile => Targeting::NearestEnemy,
+            WeaponKind::Ember => Targeting::RandomDirection,
+            WeaponKind::OrbitingFlame => Targeting::Orbit,
+        }
+    }
+
+    pub fn levels(&self) -> &'static [WeaponLevel] {
+        match self {
+            WeaponKind::MagicMissile => &MAGIC_MISSILE_LEVELS,
+            WeaponKind::Ember => &EMBER_LEVELS,
+            WeaponKind::OrbitingFlame => &ORBITING_FLAME_LEVELS,
+        }
+    }
+}
+
+const MAGIC_MISSILE_LEVELS: [WeaponLevel; 5] = [
+    level(1, 0, 1.),
+    level(2, 0, 1.),
+    level(2, 1, 1.),
+    level(3, 1, 1.25),
+    level(4, 2, 1.5),
+];
+
+const EMBER_LEVELS: [WeaponLevel; 5] = [
+    level(2, 0, 1.),
+    level(3, 0, 1.),
+    level(4, 1, 1.),
+    level(5, 1, 1.3),
+    level(6, 2, 1.5),
+];
+
+const ORBITING_FLAME_LEVELS: [WeaponLevel; 5] = [
+    level(1, u32::MAX, 1.),
+    level(2, u32::MAX, 1.),
+    level(3, u32::MAX, 1.2),
+    level(4, u32::MAX, 1.4),
+    level(5, u32::MAX, 1.6),
+];
+
+pub struct Weapon {
+    pub kind: WeaponKind,
+    /// Index into `WeaponKind::levels`.
+    pub level: usize,
+    spell: Handle<SpellDefinition>,
+    timer: Timer,
+}
+
+impl Weapon {
+    pub fn new(kind: WeaponKind, asset_server: &AssetServer) -> Self {
+        Weapon {
+            kind,
+            level: 0,
+            spell: asset_server.load(kind.spell_path()),
+            timer: Timer::default(),
+        }
+    }
+
+    pub fn is_max_level(&self) -> bool {
+        self.level + 1 >= self.kind.levels().len()
+    }
+}
+
+/// Weapons that fire on their own timers, without any input.
+#[derive(Component)]
+pub struct Weapons {
+    pub weapons: Vec<Weapon>,
+}
+
+impl Weapons {
+    pub fn get(&self, kind: WeaponKind) -> Option<&Weapon> {
+        self.weapons.iter().find(|weapon| weapon.kind == kind)
+    }
+
+    /// Levels up `kind`, or adds it at level 1 if the player doesn't have it.
+    pub fn upgrade(&mut self, kind: WeaponKind, asset_server: &AssetServer) {
+        match self.weapons.iter_mut().find(|weapon| weapon.kind == kind) {
+            Some(weapon) if !weapon.is_max_level() => weapon.level += 1,
+            Some(_) => {}
+            None => self.weapons.push(Weapon::new(kind, asset_server)),
+        }
+    }
+}
+
+/// Keeps a projectile circling the player.
+#[derive(Component)]
+struct Orbit {
+    angle: f32,
+    angular_speed: f32,
+}
+
+pub struct WeaponPlugin;
+
+impl Plugin for WeaponPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            (
+                fire_weapons.in_set(RngSet::Weapons),
+                move_orbiting_particles,
+            )
+                .in_set(OnUpdate(AppState::Playing)),
+        );
+    }
+}
+
+fn fire_weapons(
+    mut commands: Commands,
+    mut player_query: Query<(&Transform, &Stats, &mut Weapons), With<Player>>,
+    spatial_hash: Res<EnemySpatialHash>,
+    spell_definitions: Res<Assets<SpellDefinition>>,
+    mut rng: ResMut<GameRng>,
+    time: Res<Time>,
+) {
+    let (transform, stats, mut weapons) = player_query.single_mut();
+    let origin = transform.translation;
+    for weapon in weapons.weapons.iter_mut() {
+        weapon.timer.tick(time.delta());
+        if !weapon.timer.finished() {
+            continue;
+        }
+        let Some(definition) = spell_definitions.get(&weapon.spell) else {
+            continue;
+        };
+        let targeting = weapon.kind.targeting();
+        let nearest_enemy = spatial_hash
+            .nearest(origin.truncate(), TARGET_RANGE)
+            .map(|(_, position, _)| *position - origin.truncate());
+        if targeting == Targeting::NearestEnemy && nearest_enemy.is_none() {
+            continue;
+        }
+        weapon.timer = Timer::from_seconds(
+            definition.cooldown * stats.get(Stat::Cooldown),
+            TimerMode::Once,
+        );
+
+        let weapon_level = &weapon.kind.levels()[weapon.level];
+        let count =
+            weapon_level.projectiles + stats.get(Stat::ProjectileCount).round() as usize - 1;
+        let damage = (definition.damage as f32 * stats.get(Stat::Damage)).round() as usize;
+        let speed = definition.speed * stats.get(Stat::ProjectileSpeed);
+        for index in 0..count {
+            let (direction, position) = match targeting {
+                Targeting::NearestEnemy => {
+                    let angle = (index as f32 - (count - 1) as f32 / 2.) * SPREAD_ANGLE;
+                    let target = nearest_enemy.unwrap().normalize();
+                    (Vec2::from_angle(angle).rotate(target), origin)
+                }
+                Targeting::RandomDirection => (Vec2::from_angle(rng.gen_range(0. ..TAU)), origin),
+                Targeting::Orbit => {
+                    let direction = Vec2::from_angle(index as f32 / count as f32 * TAU);
+                    (
+                        direction.perp(),
+                        origin + (direction * ORBIT_RADIUS).extend(0.),
+                    )
+                }
+            };
+            let mut particle = commands.spawn((
+                particle_bundle(definition, position, direction, speed, weapon_level.area),
+                CollisionGroups::new(PLAYER_SPELL_GROUP, ENEMY_GROUP | WALL_GROUP),
+                Spell::new(damage, definition.damage_type)
+                    .with_pierce(weapon_level.pierce)
+                    .with_crit_chance(stats.get(Stat::CritChance)),
+            ));
+            if targeting == Targeting::Orbit {
+                // orbiting projectiles are moved by hand instead of by their velocity,
+                // and pass through walls
+                particle.insert((
+                    CollisionGroups::new(PLAYER_SPELL_GROUP, ENEMY_GROUP),
+                    Velocity::zero(),
+                    Orbit {
+                        angle: index as f32 / count as f32 * TAU,
+                        angular_speed: speed,
+                    },
+                ));
+            }
+        }
+    }
+}
+
+fn move_orbiting_particles(
+    player_query: Query<&Transform, With<Player>>,
+    mut query: Query<(&mut Transform, &mut Orbit), Without<Player>>,
+    time: Res<Time>,
+) {
+    let player_translation = player_query.single().translation;
+    for (mut transform, mut orbit) in query.iter_mut() {
+        orbit.angle = (orbit.angle + orbit.angular_speed * time.delta_seconds()) % TAU;
+        let direction = Vec2::from_angle(orbit.angle);
+        transform.translation = player_translation + (direction * ORBIT_RADIUS).extend(0.);
+        transform.rotation = Quat::from_rotation_arc(Vec3::X, direction.perp().extend(0.));
+    }
+}